@@ -0,0 +1,220 @@
+use std::process::Command;
+
+use argh::SubCommands;
+
+use crate::argv::SubCommand;
+use crate::config;
+
+const KAMP_ALIAS: &str = "KAMP_ALIAS_";
+
+// options of Kampliment which take a value, needed to find subcommand position
+const OPTIONS_WITH_VALUE: [&str; 7] = [
+    "-s",
+    "--session",
+    "-c",
+    "--client",
+    "-t",
+    "--timeout",
+    "--error-format",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Expansion {
+    /// arguments with alias replaced by kamp arguments
+    Args(Vec<String>),
+    /// alias starting with '!' is a shell command run with the rest of arguments,
+    /// options preceding the alias are meant for kamp commands it runs
+    Shell {
+        command: String,
+        options: Vec<String>,
+        args: Vec<String>,
+    },
+}
+
+/// Expands alias in subcommand position of args, program name excluded.
+/// Builtin subcommands can't be shadowed.
+pub(super) fn expand(
+    mut args: Vec<String>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Expansion, String> {
+    let mut iter = args.iter().enumerate();
+    let pos = loop {
+        match iter.next() {
+            Some((_, arg)) if OPTIONS_WITH_VALUE.contains(&arg.as_str()) => {
+                iter.next();
+            }
+            Some((_, arg)) if arg.starts_with('-') => {}
+            Some((i, _)) => break i,
+            None => return Ok(Expansion::Args(args)),
+        }
+    };
+    let name = &args[pos];
+    if SubCommand::COMMANDS.iter().any(|c| c.name == name) {
+        return Ok(Expansion::Args(args));
+    }
+    let Some(value) = lookup(name) else {
+        return Ok(Expansion::Args(args));
+    };
+    if let Some(command) = value.strip_prefix('!') {
+        let rest = args.split_off(pos + 1);
+        args.truncate(pos);
+        return Ok(Expansion::Shell {
+            command: command.to_owned(),
+            options: args,
+            args: rest,
+        });
+    }
+    let words = split(&value).map_err(|e| format!("alias {name}: {e}"))?;
+    args.splice(pos..=pos, words);
+    Ok(Expansion::Args(args))
+}
+
+/// Looks up alias in KAMP_ALIAS_<NAME> env variable, where dashes of the name
+/// are replaced with underscores, then in the config file.
+pub(super) fn lookup(name: &str) -> Option<String> {
+    let var = format!("{KAMP_ALIAS}{}", name.to_uppercase().replace('-', "_"));
+    std::env::var(var)
+        .ok()
+        .filter(|s| !s.is_empty())
+        .or_else(|| config::get().alias.get(name).cloned())
+}
+
+/// Command to run shell alias, args are available as positional parameters
+pub(super) fn shell(command: &str, args: &[String]) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(format!("{command} \"$@\""))
+        .arg(command)
+        .args(args);
+    cmd
+}
+
+// splits words the way POSIX shell does, without expansions
+fn split(s: &str) -> Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => w.push(c),
+                        None => return Err("missing closing quote"),
+                    }
+                }
+            }
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => w.push(c),
+                            Some(c) => {
+                                w.push('\\');
+                                w.push(c);
+                            }
+                            None => return Err("missing closing quote"),
+                        },
+                        Some(c) => w.push(c),
+                        None => return Err("missing closing quote"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash"),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|&s| s.to_owned()).collect()
+    }
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split(r#"get -b '*' opt "file type" a\ b ''"#),
+            Ok(strings(&["get", "-b", "*", "opt", "file type", "a b", ""]))
+        );
+        assert_eq!(split(r#""a\"b\n""#), Ok(strings(&["a\"b\\n"])));
+        assert_eq!(split("  "), Ok(vec![]));
+        assert!(split("'a").is_err());
+        assert!(split("\"a").is_err());
+        assert!(split("a\\").is_err());
+    }
+    #[test]
+    fn test_expand() {
+        let lookup = |name: &str| match name {
+            "ft" => Some("get -b '*' opt filetype".to_owned()),
+            "fts" => Some("!kamp ft | sort -u".to_owned()),
+            "list" => Some("ctx".to_owned()),
+            _ => None,
+        };
+        assert_eq!(
+            expand(strings(&["-s", "ft", "ft", "-z"]), lookup),
+            Ok(Expansion::Args(strings(&[
+                "-s", "ft", "get", "-b", "*", "opt", "filetype", "-z"
+            ])))
+        );
+        assert_eq!(
+            expand(strings(&["-s", "fts", "fts", "a"]), lookup),
+            Ok(Expansion::Shell {
+                command: "kamp ft | sort -u".to_owned(),
+                options: strings(&["-s", "fts"]),
+                args: strings(&["a"]),
+            })
+        );
+        assert_eq!(
+            expand(strings(&["list", "ft"]), lookup),
+            Ok(Expansion::Args(strings(&["list", "ft"])))
+        );
+        assert_eq!(
+            expand(strings(&["nope"]), lookup),
+            Ok(Expansion::Args(strings(&["nope"])))
+        );
+    }
+    #[test]
+    fn test_options_with_value() {
+        use crate::argv::Kampliment;
+        use argh::FromArgs;
+        let help = Kampliment::from_args(&["kamp"], &["--help"])
+            .expect_err("help exits early")
+            .output;
+        let options = help
+            .split("Options:")
+            .nth(1)
+            .and_then(|s| s.split("Commands:").next())
+            .expect("options section");
+        let flags: Vec<&str> = options
+            .lines()
+            .filter_map(|line| line.trim_start().split("  ").next())
+            .flat_map(|flags| flags.split(", "))
+            .filter(|flag| flag.starts_with('-') && *flag != "--help")
+            .collect();
+        for flag in &flags {
+            // an option taking value fails without one, a switch doesn't
+            let takes_value = Kampliment::from_args(&["kamp"], &[flag])
+                .is_err_and(|e| e.output.starts_with("No value provided"));
+            assert_eq!(
+                OPTIONS_WITH_VALUE.contains(flag),
+                takes_value,
+                "{flag} in OPTIONS_WITH_VALUE"
+            );
+        }
+        for option in OPTIONS_WITH_VALUE {
+            assert!(flags.contains(&option), "{option} is not an option of kamp");
+        }
+    }
+}
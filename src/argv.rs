@@ -1,4 +1,6 @@
+use crate::config;
 use argh::{FromArgValue, FromArgs};
+use std::time::Duration;
 
 /// Kakoune kampliment
 #[derive(FromArgs, PartialEq, Debug)]
@@ -11,6 +13,11 @@ pub(super) struct Kampliment {
     #[argh(option, short = 'c')]
     pub client: Option<String>,
 
+    /// pick session whose working directory is the nearest ancestor of the current one,
+    /// done by default if no session is set
+    #[argh(switch, short = 'p')]
+    pub project: bool,
+
     /// print version and exit
     #[argh(switch, short = 'v')]
     pub version: bool,
@@ -19,10 +26,90 @@ pub(super) struct Kampliment {
     #[argh(switch, short = 'd')]
     pub debug: bool,
 
+    /// error output format (text|json) default=text
+    #[argh(
+        option,
+        default = "config::get().error_format.unwrap_or(ErrorFormat::Text)"
+    )]
+    pub error_format: ErrorFormat,
+
+    /// abort request after duration, e.g. 500ms, 2s, 1m (env KAMP_TIMEOUT)
+    #[argh(option, short = 't')]
+    pub timeout: Option<Timeout>,
+
     #[argh(subcommand)]
     pub subcommand: Option<SubCommand>,
 }
 
+/// Parses arguments the same way `argh::from_env` does, the program name excluded,
+/// so that they can be rewritten beforehand.
+pub(super) fn from_args(cmd: &str, args: &[String]) -> Kampliment {
+    let cmd = std::path::Path::new(cmd)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(cmd);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    Kampliment::from_args(&[cmd], &args).unwrap_or_else(|early_exit| {
+        std::process::exit(match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                0
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {cmd} --help for more information.",
+                    early_exit.output
+                );
+                1
+            }
+        })
+    })
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub(super) enum ErrorFormat {
+    Text,
+    Json,
+}
+
+impl FromArgValue for ErrorFormat {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        Ok(match value {
+            "text" => ErrorFormat::Text,
+            "json" => ErrorFormat::Json,
+            _ => {
+                return Err("expected one of 'text', 'json'".to_owned());
+            }
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub(super) struct Timeout(pub Duration);
+
+impl FromArgValue for Timeout {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        let (num, unit) = value
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .map_or((value, "s"), |i| value.split_at(i));
+        let num: f64 = num
+            .parse()
+            .map_err(|_| format!("invalid duration: {value:?}"))?;
+        let secs = match unit {
+            "ms" => num / 1000.0,
+            "s" => num,
+            "m" => num * 60.0,
+            _ => return Err("expected duration unit one of 'ms', 's', 'm'".to_owned()),
+        };
+        match Duration::try_from_secs_f64(secs) {
+            // every request would time out right away
+            Ok(d) if d.is_zero() => Err("timeout must be greater than zero".to_owned()),
+            Ok(d) => Ok(Timeout(d)),
+            Err(_) => Err(format!("invalid duration: {value:?}")),
+        }
+    }
+}
+
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand)]
 pub(super) enum SubCommand {
@@ -33,8 +120,15 @@ pub(super) enum SubCommand {
     Kill(kill::Options),
     List(list::Options),
     Get(get::Options),
+    Set(set::Options),
     Cat(cat::Options),
+    Put(put::Options),
+    Fifo(fifo::Options),
+    Filter(filter::Options),
+    Watch(watch::Options),
     Ctx(ctx::Options),
+    Doctor(doctor::Options),
+    Start(start::Options),
 }
 
 pub(super) mod init {
@@ -83,7 +177,7 @@ mod attach {
     }
 }
 
-mod edit {
+pub(super) mod edit {
     use super::*;
     /// Edit a file in context client or create new one if
     /// client isn't specified or -n switch is provided.
@@ -98,10 +192,45 @@ mod edit {
         #[argh(switch, short = 'f')]
         pub focus: bool,
 
-        /// path to file
+        /// wait until file is closed in client in context or the client quits
+        #[argh(switch, short = 'w')]
+        pub wait: bool,
+
+        /// start session in context if it is not running
+        #[argh(switch)]
+        pub spawn: bool,
+
+        /// create new client in a new window through the session's terminal integration
+        #[argh(switch)]
+        pub new_window: bool,
+
+        /// create new client in a split (horizontal|vertical) through the session's
+        /// terminal integration
+        #[argh(option)]
+        pub split: Option<Split>,
+
+        /// path to file, optionally as file:line[:col][-line:col] or preceded by +line[:col]
         #[argh(positional, arg_name = "file")]
         pub files: Vec<String>,
     }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum Split {
+        Horizontal,
+        Vertical,
+    }
+
+    impl FromArgValue for Split {
+        fn from_arg_value(value: &str) -> Result<Self, String> {
+            Ok(match value {
+                "horizontal" => Split::Horizontal,
+                "vertical" => Split::Vertical,
+                _ => {
+                    return Err("expected one of 'horizontal', 'vertical'".to_owned());
+                }
+            })
+        }
+    }
 }
 
 mod send {
@@ -136,7 +265,7 @@ mod kill {
     }
 }
 
-mod list {
+pub(super) mod list {
     use super::*;
     /// List a session in context.
     #[derive(FromArgs, PartialEq, Debug)]
@@ -145,6 +274,34 @@ mod list {
         /// all sessions
         #[argh(switch, short = 'a')]
         pub all: bool,
+
+        /// output format (json|tsv|debug) default=debug
+        #[argh(
+            option,
+            short = 'f',
+            default = "config::get().list.format.unwrap_or(Format::Debug)"
+        )]
+        pub format: Format,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum Format {
+        Json,
+        Tsv,
+        Debug,
+    }
+
+    impl FromArgValue for Format {
+        fn from_arg_value(value: &str) -> Result<Self, String> {
+            Ok(match value {
+                "json" => Format::Json,
+                "tsv" => Format::Tsv,
+                "debug" => Format::Debug,
+                _ => {
+                    return Err("expected one of 'json', 'tsv', 'debug'".to_owned());
+                }
+            })
+        }
     }
 }
 
@@ -166,6 +323,8 @@ pub(super) mod get {
         pub subcommand: SubCommand,
     }
 
+    pub use batch::Key;
+
     #[derive(FromArgs, PartialEq, Debug)]
     #[argh(subcommand)]
     pub enum SubCommand {
@@ -173,15 +332,21 @@ pub(super) mod get {
         Option(option::Options),
         Register(register::Options),
         Shell(shell::Options),
+        Selection(selection::Options),
+        Batch(batch::Options),
     }
 
-    #[derive(PartialEq, Debug)]
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
     pub enum QuotingMethod {
         Raw,
         Kakoune,
         Shell,
     }
 
+    fn default_quoting() -> QuotingMethod {
+        config::get().get.quoting.unwrap_or(QuotingMethod::Kakoune)
+    }
+
     impl argh::FromArgValue for QuotingMethod {
         fn from_arg_value(value: &str) -> Result<Self, String> {
             Ok(match value {
@@ -203,7 +368,7 @@ pub(super) mod get {
         pub struct Options {
             /// quote output (raw|kakoune|shell) default=kakoune;
             /// choosing one other than kakoune will produce verbatim output
-            #[argh(option, short = 'q', default = "QuotingMethod::Kakoune")]
+            #[argh(option, short = 'q', default = "default_quoting()")]
             pub quoting: QuotingMethod,
 
             /// get value of key of type map, implies quoting=kakoune
@@ -228,7 +393,7 @@ pub(super) mod get {
         pub struct Options {
             /// quote output (raw|kakoune|shell) default=kakoune;
             /// choosing one other than kakoune will produce verbatim output
-            #[argh(option, short = 'q', default = "QuotingMethod::Kakoune")]
+            #[argh(option, short = 'q', default = "default_quoting()")]
             pub quoting: QuotingMethod,
 
             /// get value of key of type map, implies quoting=kakoune
@@ -253,7 +418,7 @@ pub(super) mod get {
         pub struct Options {
             /// quote output (raw|kakoune|shell) default=kakoune;
             /// choosing one other than kakoune will produce verbatim output
-            #[argh(option, short = 'q', default = "QuotingMethod::Kakoune")]
+            #[argh(option, short = 'q', default = "default_quoting()")]
             pub quoting: QuotingMethod,
 
             /// print raw output as it is
@@ -266,6 +431,84 @@ pub(super) mod get {
         }
     }
 
+    #[derive(PartialEq, Debug)]
+    pub enum Format {
+        Plain,
+        Json,
+    }
+
+    impl argh::FromArgValue for Format {
+        fn from_arg_value(value: &str) -> Result<Self, String> {
+            Ok(match value {
+                "plain" => Format::Plain,
+                "json" => Format::Json,
+                _ => {
+                    return Err("expected one of 'plain', 'json'".to_owned());
+                }
+            })
+        }
+    }
+
+    mod selection {
+        use super::*;
+        /// Get selections with their coordinates
+        #[derive(FromArgs, PartialEq, Debug)]
+        #[argh(subcommand, name = "sel")]
+        pub struct Options {
+            /// output format (plain|json) default=plain
+            #[argh(option, short = 'f', default = "Format::Plain")]
+            pub format: Format,
+        }
+    }
+
+    mod batch {
+        use super::*;
+        /// Get several values, options and registers at once
+        #[derive(FromArgs, PartialEq, Debug)]
+        #[argh(subcommand, name = "batch")]
+        pub struct Options {
+            /// output format (plain|json) default=plain;
+            /// plain prints 'key<TAB>value' per value
+            #[argh(option, short = 'f', default = "Format::Plain")]
+            pub format: Format,
+
+            /// keys to query as val:<name>, opt:<name> or reg:<name>
+            #[argh(positional, arg_name = "key")]
+            pub keys: Vec<Key>,
+        }
+
+        #[derive(PartialEq, Eq, Debug, Clone)]
+        pub enum Key {
+            Val(String),
+            Opt(String),
+            Reg(String),
+        }
+
+        impl argh::FromArgValue for Key {
+            fn from_arg_value(value: &str) -> Result<Self, String> {
+                match value.split_once(':') {
+                    Some((_, "")) => Err(format!("empty name in {value:?}")),
+                    Some(("val", name)) => Ok(Key::Val(name.into())),
+                    Some(("opt", name)) => Ok(Key::Opt(name.into())),
+                    Some(("reg", name)) => Ok(Key::Reg(name.into())),
+                    _ => Err(format!(
+                        "expected one of 'val:<name>', 'opt:<name>', 'reg:<name>', got {value:?}"
+                    )),
+                }
+            }
+        }
+
+        impl std::fmt::Display for Key {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    Key::Val(name) => write!(f, "val:{name}"),
+                    Key::Opt(name) => write!(f, "opt:{name}"),
+                    Key::Reg(name) => write!(f, "reg:{name}"),
+                }
+            }
+        }
+    }
+
     mod shell {
         use super::*;
         /// Evaluate shell command as %sh<command>.
@@ -283,7 +526,111 @@ pub(super) mod get {
     }
 }
 
-mod cat {
+pub(super) mod set {
+    use super::*;
+    /// Set state of a session in context.
+    #[derive(FromArgs, PartialEq, Debug)]
+    #[argh(subcommand, name = "set")]
+    pub struct Options {
+        /// buffer context or '*' for all non-debug buffers
+        #[argh(option, short = 'b', long = "buffer", arg_name = "buffer")]
+        pub buffers: Vec<String>,
+
+        /// read one more value from stdin
+        #[argh(switch, short = 'i')]
+        pub stdin: bool,
+
+        /// split stdin by null character into several values, implies -i
+        #[argh(switch, short = 'z')]
+        pub zplit: bool,
+
+        #[argh(subcommand)]
+        pub subcommand: SubCommand,
+    }
+
+    #[derive(FromArgs, PartialEq, Debug)]
+    #[argh(subcommand)]
+    pub enum SubCommand {
+        Option(option::Options),
+        Register(register::Options),
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum Scope {
+        Global,
+        Buffer,
+        Window,
+    }
+
+    impl FromArgValue for Scope {
+        fn from_arg_value(value: &str) -> Result<Self, String> {
+            Ok(match value {
+                "global" => Scope::Global,
+                "buffer" => Scope::Buffer,
+                "window" => Scope::Window,
+                _ => {
+                    return Err("expected one of 'global', 'buffer', 'window'".to_owned());
+                }
+            })
+        }
+    }
+
+    impl std::fmt::Display for Scope {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Scope::Global => write!(f, "global"),
+                Scope::Buffer => write!(f, "buffer"),
+                Scope::Window => write!(f, "window"),
+            }
+        }
+    }
+
+    mod option {
+        use super::*;
+        /// Set option as set-option <scope> <name> <values>
+        #[derive(FromArgs, PartialEq, Debug)]
+        #[argh(subcommand, name = "opt")]
+        pub struct Options {
+            /// option scope (global|buffer|window) default=global
+            #[argh(option, default = "Scope::Global")]
+            pub scope: Scope,
+
+            /// add values to list, set, map or int option
+            #[argh(switch, short = 'a')]
+            pub add: bool,
+
+            /// remove values from list, set, map or int option
+            #[argh(switch, short = 'r')]
+            pub remove: bool,
+
+            /// option name to set (required)
+            #[argh(positional)]
+            pub name: String,
+
+            /// values to set
+            #[argh(positional, greedy)]
+            pub values: Vec<String>,
+        }
+    }
+
+    mod register {
+        use super::*;
+        /// Set register as set-register <name> <values>
+        #[derive(FromArgs, PartialEq, Debug)]
+        #[argh(subcommand, name = "reg")]
+        pub struct Options {
+            /// register name to set (required)
+            #[argh(positional)]
+            pub name: String,
+
+            /// values to set
+            #[argh(positional, greedy)]
+            pub values: Vec<String>,
+        }
+    }
+}
+
+pub(super) mod cat {
     use super::*;
     /// Print buffer content.
     #[derive(FromArgs, PartialEq, Debug)]
@@ -292,6 +639,183 @@ mod cat {
         /// buffer context or '*' for all non-debug buffers
         #[argh(option, short = 'b', long = "buffer", arg_name = "buffer")]
         pub buffers: Vec<String>,
+
+        /// print only lines in range <start>:<end>, either may be omitted
+        #[argh(option, short = 'l')]
+        pub lines: Option<LineRange>,
+
+        /// print selections instead of the whole buffer
+        #[argh(switch, short = 's')]
+        pub selections: bool,
+
+        /// split selections by null character instead of new line
+        #[argh(switch, short = 'z')]
+        pub zplit: bool,
+
+        /// prefix each line with its number
+        #[argh(switch, short = 'n')]
+        pub numbered: bool,
+
+        /// frame content of each buffer (header|nul|json): header prints
+        /// '==> bufname <==' line, nul prints 'bufname\0content\0',
+        /// json prints '{"bufname":..,"line":..,"text":..}' line
+        #[argh(option, short = 'f')]
+        pub frame: Option<Frame>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum Frame {
+        Header,
+        Nul,
+        Json,
+    }
+
+    impl FromArgValue for Frame {
+        fn from_arg_value(value: &str) -> Result<Self, String> {
+            Ok(match value {
+                "header" => Frame::Header,
+                "nul" => Frame::Nul,
+                "json" => Frame::Json,
+                _ => {
+                    return Err("expected one of 'header', 'nul', 'json'".to_owned());
+                }
+            })
+        }
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+    pub struct LineRange {
+        pub start: Option<usize>,
+        pub end: Option<usize>,
+    }
+
+    impl FromArgValue for LineRange {
+        fn from_arg_value(value: &str) -> Result<Self, String> {
+            let parse = |s: &str| -> Result<Option<usize>, String> {
+                match s {
+                    "" => Ok(None),
+                    s => match s.parse() {
+                        Ok(0) | Err(_) => Err(format!("invalid line number: {s:?}")),
+                        Ok(n) => Ok(Some(n)),
+                    },
+                }
+            };
+            let range = match value.split_once(':') {
+                Some((start, end)) => LineRange {
+                    start: parse(start)?,
+                    end: parse(end)?,
+                },
+                None if value.is_empty() => return Err("empty line range".to_owned()),
+                None => {
+                    let line = parse(value)?;
+                    LineRange {
+                        start: line,
+                        end: line,
+                    }
+                }
+            };
+            match range {
+                LineRange {
+                    start: Some(start),
+                    end: Some(end),
+                } if start > end => Err("start of range is greater than its end".to_owned()),
+                range => Ok(range),
+            }
+        }
+    }
+}
+
+mod put {
+    use super::*;
+    /// Put stdin or a file into buffer content.
+    #[derive(FromArgs, PartialEq, Debug)]
+    #[argh(subcommand, name = "put")]
+    pub struct Options {
+        /// buffer context or '*' for all non-debug buffers
+        #[argh(option, short = 'b', long = "buffer", arg_name = "buffer")]
+        pub buffers: Vec<String>,
+
+        /// replace whole buffer content
+        #[argh(switch, short = 'R')]
+        pub replace_buffer: bool,
+
+        /// replace each selection
+        #[argh(switch, short = 'r')]
+        pub replace_selections: bool,
+
+        /// append to the end of buffer
+        #[argh(switch, short = 'a')]
+        pub append: bool,
+
+        /// insert before each selection
+        #[argh(switch, short = 'i')]
+        pub insert_before: bool,
+
+        /// file to read instead of stdin
+        #[argh(positional)]
+        pub file: Option<String>,
+    }
+}
+
+mod fifo {
+    use super::*;
+    /// Pipe stdin into a fifo buffer in context client.
+    #[derive(FromArgs, PartialEq, Debug)]
+    #[argh(subcommand, name = "fifo")]
+    pub struct Options {
+        /// scroll the buffer as data arrives
+        #[argh(switch, short = 's')]
+        pub scroll: bool,
+
+        /// make the buffer read-only
+        #[argh(switch, short = 'r')]
+        pub readonly: bool,
+
+        /// append to the buffer instead of replacing its content
+        #[argh(switch, short = 'a')]
+        pub append: bool,
+
+        /// buffer name to be wrapped into '*', default=kamp-fifo
+        #[argh(positional, default = r#"String::from("kamp-fifo")"#)]
+        pub name: String,
+    }
+}
+
+mod filter {
+    use super::*;
+    /// Pipe selections in context client, or the whole buffer,
+    /// through command run by kamp and replace them with its output.
+    #[derive(FromArgs, PartialEq, Debug)]
+    #[argh(subcommand, name = "filter")]
+    pub struct Options {
+        /// buffer to filter as a whole instead of selections
+        #[argh(option, short = 'b', long = "buffer", arg_name = "buffer")]
+        pub buffers: Vec<String>,
+
+        /// command to run
+        #[argh(positional, greedy)]
+        pub command: Vec<String>,
+    }
+}
+
+mod watch {
+    use super::*;
+    /// Print events of a hook installed in a session until interrupted.
+    #[derive(FromArgs, PartialEq, Debug)]
+    #[argh(subcommand, name = "watch")]
+    pub struct Options {
+        /// output format (plain|json) default=plain;
+        /// plain prints 'hook<TAB>client<TAB>bufname<TAB>param' per event
+        #[argh(option, short = 'f', default = "get::Format::Plain")]
+        pub format: get::Format,
+
+        /// hook name, e.g. BufWritePost
+        #[argh(positional)]
+        pub hook: String,
+
+        /// hook parameter filter regex, default=.*
+        #[argh(positional, default = r#"String::from(".*")"#)]
+        pub filter: String,
     }
 }
 
@@ -306,3 +830,58 @@ mod ctx {
         pub client: bool,
     }
 }
+
+mod doctor {
+    use super::*;
+    /// Check kamp setup and print what is wrong with it.
+    #[derive(FromArgs, PartialEq, Debug)]
+    #[argh(subcommand, name = "doctor")]
+    pub struct Options {}
+}
+
+mod start {
+    use super::*;
+    /// Start a daemon session unless it is running and print its name.
+    #[derive(FromArgs, PartialEq, Debug)]
+    #[argh(subcommand, name = "start")]
+    pub struct Options {
+        /// session name, defaults to session in context
+        #[argh(positional)]
+        pub session: Option<String>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_line_range() {
+        use cat::LineRange;
+        let parse = |s| LineRange::from_arg_value(s).map(|r| (r.start, r.end));
+        assert_eq!(parse("100:200"), Ok((Some(100), Some(200))));
+        assert_eq!(parse("100:"), Ok((Some(100), None)));
+        assert_eq!(parse(":200"), Ok((None, Some(200))));
+        assert_eq!(parse("5"), Ok((Some(5), Some(5))));
+        assert_eq!(parse(":"), Ok((None, None)));
+        assert!(parse("").is_err());
+        assert!(parse("0:1").is_err());
+        assert!(parse("2:1").is_err());
+        assert!(parse("a:1").is_err());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let parse = |s| Timeout::from_arg_value(s).map(|t| t.0);
+        assert_eq!(parse("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse("1m"), Ok(Duration::from_secs(60)));
+        assert!(parse("").is_err());
+        assert!(parse("s").is_err());
+        assert!(parse("1h").is_err());
+        assert!(parse("-1s").is_err());
+        assert!(parse("0").is_err());
+        assert!(parse("0ms").is_err());
+    }
+}
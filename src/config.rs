@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use argh::FromArgValue;
+use serde::{Deserialize, Deserializer};
+
+use crate::argv::get::QuotingMethod;
+use crate::argv::{ErrorFormat, Timeout, list};
+
+const KAMP_CONFIG: &str = "KAMP_CONFIG";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Defaults read from `config.toml`, values given on the command line or
+/// in the environment take precedence.
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(super) struct Config {
+    pub session: Option<String>,
+    pub client: Option<String>,
+    #[serde(deserialize_with = "arg_value")]
+    pub timeout: Option<Timeout>,
+    #[serde(deserialize_with = "arg_value")]
+    pub error_format: Option<ErrorFormat>,
+    pub get: Get,
+    pub list: List,
+    pub send: Send,
+    /// name to kamp arguments, e.g. `ft = "get -b '*' opt filetype"` for `kamp ft`
+    pub alias: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Get {
+    #[serde(deserialize_with = "arg_value")]
+    pub quoting: Option<QuotingMethod>,
+}
+
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct List {
+    #[serde(deserialize_with = "arg_value")]
+    pub format: Option<list::Format>,
+}
+
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Send {
+    /// name to command, e.g. `wa = "write-all"` for `kamp send wa`
+    pub alias: HashMap<String, String>,
+}
+
+/// Loads config once, must be called before parsing arguments
+/// as their defaults depend on it.
+pub(super) fn load() -> anyhow::Result<&'static Config> {
+    if let Some(config) = CONFIG.get() {
+        return Ok(config);
+    }
+    let config = match path() {
+        Some(path) => match std::fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s).with_context(|| format!("config: {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(e).with_context(|| format!("config: {}", path.display())),
+        },
+        None => Config::default(),
+    };
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// Loaded config or default one
+pub(super) fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+// $KAMP_CONFIG, $XDG_CONFIG_HOME/kamp/config.toml or ~/.config/kamp/config.toml
+fn path() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|s| !s.is_empty());
+    if let Some(path) = var(KAMP_CONFIG) {
+        return Some(PathBuf::from(path));
+    }
+    let dir = match var("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var("HOME")?).join(".config"),
+    };
+    Some(dir.join("kamp").join("config.toml"))
+}
+
+// values are parsed the same way as on the command line
+fn arg_value<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromArgValue,
+{
+    let s = String::deserialize(deserializer)?;
+    T::from_arg_value(&s)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+            session = "main"
+            timeout = "2s"
+            error-format = "json"
+            [get]
+            quoting = "shell"
+            [list]
+            format = "tsv"
+            [send.alias]
+            wa = "write-all"
+            [alias]
+            ft = "get -b '*' opt filetype"
+            "#,
+        )?;
+        assert_eq!(config.session.as_deref(), Some("main"));
+        assert_eq!(config.client, None);
+        assert_eq!(
+            config.timeout,
+            Some(Timeout(std::time::Duration::from_secs(2)))
+        );
+        assert_eq!(config.error_format, Some(ErrorFormat::Json));
+        assert_eq!(config.get.quoting, Some(QuotingMethod::Shell));
+        assert_eq!(config.list.format, Some(list::Format::Tsv));
+        assert_eq!(config.send.alias["wa"], "write-all");
+        assert_eq!(config.alias["ft"], "get -b '*' opt filetype");
+        Ok(())
+    }
+    #[test]
+    fn test_parse_err() {
+        assert!(toml::from_str::<Config>("timeout = \"2h\"").is_err());
+        assert!(toml::from_str::<Config>("[get]\nquoting = \"json\"").is_err());
+        assert!(toml::from_str::<Config>("sesion = \"main\"").is_err());
+        assert_eq!(toml::from_str::<Config>("").ok(), Some(Config::default()));
+    }
+}
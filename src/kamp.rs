@@ -1,36 +1,192 @@
 mod cmd;
 mod context;
 mod error;
+mod json;
 mod kak;
 
-use super::argv::{Kampliment, SubCommand};
+use super::alias::{self, Expansion};
+use super::argv::get::SubCommand as GetSubCommand;
+use super::argv::{self, ErrorFormat, Kampliment, SubCommand, Timeout};
+use super::config;
+use argh::FromArgValue;
 use context::Context;
 use error::{Error, Result};
-use std::io::Write;
+use std::io::{Read, Write};
+use std::process::ExitCode;
 
 const KAKOUNE_SESSION: &str = "KAKOUNE_SESSION";
 const KAKOUNE_CLIENT: &str = "KAKOUNE_CLIENT";
+const KAMP_TIMEOUT: &str = "KAMP_TIMEOUT";
 
 pub(crate) trait Dispatcher {
     fn dispatch<W: Write>(self, ctx: Context, writer: W) -> Result<()>;
 }
 
-pub(super) fn run() -> Result<()> {
-    let kamp: Kampliment = argh::from_env();
-    if kamp.version {
-        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-        return Ok(());
-    }
+pub(super) fn run() -> ExitCode {
+    // argument defaults depend on config
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let args = std::env::args_os()
+        .map(|s| s.into_string())
+        .collect::<std::result::Result<Vec<_>, _>>();
+    let Some((cmd, args)) = args.as_deref().ok().and_then(|args| args.split_first()) else {
+        eprintln!("Error: invalid utf8 in arguments");
+        return ExitCode::FAILURE;
+    };
+    let kamp: Kampliment = match alias::expand(args.to_vec(), alias::lookup) {
+        Ok(Expansion::Args(args)) => argv::from_args(cmd, &args),
+        Ok(Expansion::Shell {
+            command,
+            options,
+            args,
+        }) => {
+            use std::os::unix::process::CommandExt;
+            let kamp = argv::from_args(cmd, &options);
+            let env = match shell_env(&kamp) {
+                Ok(env) => env,
+                Err(e) => {
+                    let code = e.exit_code();
+                    eprintln!("Error: {:?}", anyhow::Error::from(e));
+                    return ExitCode::from(code);
+                }
+            };
+            let e = alias::shell(&command, &args).envs(env).exec();
+            eprintln!("Error: {e}");
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
 
     let session = kamp
         .session
+        .clone()
         .filter(|s| !s.is_empty())
+        .or_else(|| std::env::var(KAKOUNE_SESSION).ok())
+        .or_else(|| config.session.clone());
+    let client = kamp
+        .client
+        .clone()
+        .or_else(|| std::env::var(KAKOUNE_CLIENT).ok())
+        .or_else(|| config.client.clone())
+        .filter(|c| !c.is_empty());
+
+    let error_format = kamp.error_format;
+    // errors are reported with names resolved so far
+    let mut resolved = (session.clone(), client.clone());
+    match run_with(kamp, session, client, &mut resolved) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let code = e.exit_code();
+            match error_format {
+                ErrorFormat::Text => eprintln!("Error: {:?}", anyhow::Error::from(e)),
+                ErrorFormat::Json => {
+                    let mut buf = String::new();
+                    let (session, client) = resolved;
+                    e.write_json(&mut buf, session.as_deref(), client.as_deref())
+                        .expect("write to String never fails");
+                    eprintln!("{buf}");
+                }
+            }
+            ExitCode::from(code)
+        }
+    }
+}
+
+// options preceding a shell alias, resolved the same way as for a subcommand,
+// reach kamp commands run by the alias through env
+fn shell_env(kamp: &Kampliment) -> Result<Vec<(&'static str, String)>> {
+    let mut env = Vec::new();
+    let timeout = kamp.timeout.map(|t| t.0);
+    if let Some(timeout) = timeout {
+        env.push((KAMP_TIMEOUT, format!("{}s", timeout.as_secs_f64())));
+    }
+    let session = if kamp.project {
+        let dir = std::env::current_dir()?;
+        let Some(picked) = cmd::project_session(&dir, timeout, kamp.debug)? else {
+            return Err(Error::InvalidContext("no session for the project"));
+        };
+        Some(picked)
+    } else {
+        let session = kamp.session.clone().filter(|s| !s.is_empty());
+        session.map(cmd::resolve_session).transpose()?
+    };
+    let client = kamp.client.clone().filter(|c| !c.is_empty());
+    // selectors are resolved by every command on its own
+    let in_context = session
+        .clone()
         .or_else(|| std::env::var(KAKOUNE_SESSION).ok());
+    let client = match (client, in_context) {
+        (Some(client), Some(session)) if !is_selector(&client) => {
+            let mut ctx = Context::new(session, kamp.debug);
+            ctx.set_timeout(timeout);
+            Some(cmd::resolve_client(&ctx, client)?)
+        }
+        (client, _) => client,
+    };
+    env.extend(session.map(|s| (KAKOUNE_SESSION, s)));
+    env.extend(client.map(|c| (KAKOUNE_CLIENT, c)));
+    Ok(env)
+}
+
+fn run_with(
+    kamp: Kampliment,
+    session: Option<String>,
+    client: Option<String>,
+    resolved: &mut (Option<String>, Option<String>),
+) -> Result<()> {
+    if kamp.version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    let timeout = match kamp.timeout {
+        Some(timeout) => Some(timeout.0),
+        None => match std::env::var(KAMP_TIMEOUT) {
+            Ok(s) if !s.is_empty() => Timeout::from_arg_value(&s)
+                .map(|t| Some(t.0))
+                .map_err(|e| anyhow::anyhow!("{KAMP_TIMEOUT}: {e}"))?,
+            _ => config::get().timeout.map(|t| t.0),
+        },
+    };
 
     let command = kamp
         .subcommand
         .unwrap_or_else(|| SubCommand::Ctx(Default::default()));
 
+    let (session, client) = match &command {
+        SubCommand::Init(_) | SubCommand::Start(_) => (session, client),
+        SubCommand::List(opt) if opt.all => (session, client),
+        _ if kamp.project => {
+            let dir = std::env::current_dir()?;
+            let Some(picked) = cmd::project_session(&dir, timeout, kamp.debug)? else {
+                return Err(Error::InvalidContext("no session for the project"));
+            };
+            // client in context belongs to another session, unless it is a selector
+            let client = client.filter(|c| is_selector(c) || session.as_ref() == Some(&picked));
+            (Some(picked), client)
+        }
+        // best effort, failing to find one is the same as having none;
+        // only queries, anything changing the editor state must be pointed at a session
+        _ if session.is_none() && is_query(&command) => {
+            let picked = std::env::current_dir()
+                .ok()
+                .and_then(|dir| cmd::project_session(&dir, timeout, kamp.debug).ok()?);
+            (picked, client.filter(|c| is_selector(c)))
+        }
+        // names given on the command line can be abbreviated
+        _ if kamp.session.is_some() => (session.map(cmd::resolve_session).transpose()?, client),
+        _ => (session, client),
+    };
+    *resolved = (session.clone(), client.clone());
+
     let stdout = std::io::stdout();
     let mut output = stdout.lock();
 
@@ -42,20 +198,46 @@ pub(super) fn run() -> Result<()> {
         SubCommand::List(opt) if opt.all => {
             let sessions = kak::list_sessions()?;
             let sessions = String::from_utf8(sessions)?;
-            let sessions = cmd::list_all(sessions.lines().map(String::from), kamp.debug)?;
+            let sessions = cmd::list_all(sessions.lines().map(String::from), timeout, kamp.debug)?;
             for session in sessions {
-                writeln!(output, "{session:#?}")?;
+                session.write_to(&mut output, &opt.format)?;
             }
         }
-        SubCommand::Edit(opt) if session.is_none() => {
+        SubCommand::Doctor(_) => {
+            cmd::doctor(session, client, timeout, kamp.debug, output)?;
+        }
+        SubCommand::Start(opt) => {
+            let Some(session) = opt.session.or(session) else {
+                return Err(Error::InvalidContext("session is required"));
+            };
+            cmd::start(&session, timeout, kamp.debug)?;
+            writeln!(output, "{session}")?;
+        }
+        SubCommand::Edit(opt) if session.is_none() && !opt.spawn => {
             kak::proxy(opt.files)?;
         }
         _ => {
             let Some(session) = session else {
                 return Err(Error::InvalidContext("session is required"));
             };
+            let started = match &command {
+                SubCommand::Edit(opt) if opt.spawn => cmd::start(&session, timeout, kamp.debug)?,
+                _ => false,
+            };
             let mut ctx = Context::new(session, kamp.debug);
-            if let Some(client) = kamp.client.or_else(|| std::env::var(KAKOUNE_CLIENT).ok()) {
+            ctx.set_timeout(timeout);
+            // client of another session can't be in the new one
+            if started {
+                resolved.1 = None;
+            }
+            if let (Some(client), false) = (client, started) {
+                let client = match client.strip_prefix('@') {
+                    Some(selector) => cmd::select_client(&ctx, selector)?,
+                    // name given on the command line can be abbreviated
+                    None if kamp.client.is_some() => cmd::resolve_client(&ctx, client)?,
+                    None => client,
+                };
+                resolved.1 = Some(client.clone());
                 ctx.set_client(client);
             }
             ctx.dispatch(command, output)?;
@@ -85,8 +267,30 @@ impl Dispatcher for SubCommand {
             SubCommand::Edit(opt) => {
                 let session = ctx.session();
                 let client = ctx.client();
-                let scratch = cmd::edit(ctx, opt.new, opt.focus, opt.files)?;
-                if let (Some(client), false) = (client, opt.focus) {
+                let edit_client = match (opt.new, opt.new_window || opt.split.is_some()) {
+                    (true, true) => {
+                        return Err(anyhow::anyhow!(
+                            "--new is mutually exclusive with --new-window and --split"
+                        )
+                        .into());
+                    }
+                    (false, true) if opt.wait => {
+                        return Err(anyhow::anyhow!(
+                            "--wait is mutually exclusive with --new-window and --split"
+                        )
+                        .into());
+                    }
+                    (true, false) => cmd::EditClient::New,
+                    (false, true) => cmd::EditClient::Terminal(opt.split),
+                    (false, false) => cmd::EditClient::Context,
+                };
+                let scratch = cmd::edit(ctx, edit_client, opt.focus, opt.wait, opt.files)?;
+                if let (Some(client), false, false, false) = (
+                    client,
+                    opt.focus,
+                    opt.wait,
+                    matches!(edit_client, cmd::EditClient::Terminal(_)),
+                ) {
                     writeln!(
                         writer,
                         "{} is opened in client: {client}, session: {session}",
@@ -98,10 +302,20 @@ impl Dispatcher for SubCommand {
                 if opt.command.is_empty() {
                     return Err(Error::CommandRequired);
                 }
+                // alias from config expands verbatim, the rest is quoted as usual
+                let alias = opt
+                    .command
+                    .first()
+                    .and_then(|x| config::get().send.alias.get(x));
+                let args = if alias.is_some() {
+                    &opt.command[1..]
+                } else {
+                    &opt.command[..]
+                };
                 let body = if opt.verbatim {
-                    opt.command.join(" ")
+                    args.join(" ")
                 } else {
-                    opt.command.iter().fold(String::new(), |mut buf, x| {
+                    args.iter().fold(String::new(), |mut buf, x| {
                         if !buf.is_empty() {
                             buf.push(' ');
                         }
@@ -120,24 +334,112 @@ impl Dispatcher for SubCommand {
                         buf
                     })
                 };
+                let body = match alias {
+                    Some(alias) if body.is_empty() => alias.clone(),
+                    Some(alias) => format!("{alias} {body}"),
+                    None => body,
+                };
                 ctx.send(body, to_buffer_ctx(opt.buffers)).map(drop)?;
             }
-            SubCommand::List(_) => {
+            SubCommand::List(opt) => {
                 let session = cmd::list_current(ctx)?;
-                writeln!(writer, "{session:#?}")?;
+                session.write_to(writer, &opt.format)?;
             }
             SubCommand::Kill(opt) => {
                 ctx.send_kill(opt.exit_status)?;
             }
             SubCommand::Get(opt) => {
                 let split_by = if opt.zplit { '\0' } else { '\n' };
-                let items = ctx.query_kak(opt.subcommand, to_buffer_ctx(opt.buffers))?;
-                for item in items {
-                    write!(writer, "{item}{split_by}")?;
+                match opt.subcommand {
+                    GetSubCommand::Selection(o) => {
+                        for sel in cmd::selections(&ctx, to_buffer_ctx(opt.buffers))? {
+                            sel.write_to(&mut writer, &o.format, split_by)?;
+                        }
+                    }
+                    GetSubCommand::Batch(o) => {
+                        if o.keys.is_empty() {
+                            return Err(anyhow::anyhow!("at least one key is required").into());
+                        }
+                        for batch in cmd::batch(&ctx, o.keys, to_buffer_ctx(opt.buffers))? {
+                            batch.write_to(&mut writer, &o.format, split_by)?;
+                        }
+                    }
+                    subcommand => {
+                        let items = ctx.query_kak(subcommand, to_buffer_ctx(opt.buffers))?;
+                        for item in items {
+                            write!(writer, "{item}{split_by}")?;
+                        }
+                    }
                 }
             }
+            SubCommand::Set(opt) => {
+                let stdin = if opt.stdin || opt.zplit {
+                    let mut buf = String::new();
+                    std::io::stdin().read_to_string(&mut buf)?;
+                    if opt.zplit {
+                        let mut values: Vec<String> = buf.split('\0').map(String::from).collect();
+                        if values.last().is_some_and(|s| s.is_empty()) {
+                            values.pop();
+                        }
+                        Some(values)
+                    } else {
+                        Some(vec![buf])
+                    }
+                } else {
+                    None
+                };
+                cmd::set(&ctx, opt.subcommand, stdin, to_buffer_ctx(opt.buffers))?;
+            }
+            SubCommand::Put(opt) => {
+                let modes = [
+                    (opt.replace_buffer, cmd::PutMode::ReplaceBuffer),
+                    (opt.replace_selections, cmd::PutMode::ReplaceSelections),
+                    (opt.append, cmd::PutMode::Append),
+                    (opt.insert_before, cmd::PutMode::InsertBefore),
+                ];
+                let mut modes = modes
+                    .into_iter()
+                    .filter_map(|(on, mode)| on.then_some(mode));
+                let (Some(mode), None) = (modes.next(), modes.next()) else {
+                    return Err(anyhow::anyhow!(
+                        "exactly one of --replace-buffer, --replace-selections, --append, --insert-before is required"
+                    )
+                    .into());
+                };
+                let stdin = std::io::stdin().lock();
+                cmd::put(&ctx, mode, opt.file, stdin, to_buffer_ctx(opt.buffers))?;
+            }
+            SubCommand::Fifo(opt) => {
+                let stdin = std::io::stdin().lock();
+                cmd::fifo(&ctx, &opt.name, opt.scroll, opt.readonly, opt.append, stdin)?;
+            }
+            SubCommand::Filter(opt) => {
+                cmd::filter(&ctx, opt.command, to_buffer_ctx(opt.buffers))?;
+            }
+            SubCommand::Watch(opt) => {
+                cmd::watch(&ctx, &opt.hook, &opt.filter, &opt.format, writer)?;
+            }
             SubCommand::Cat(opt) => {
-                let res = cmd::cat(ctx, to_buffer_ctx(opt.buffers))?;
+                let range = match (opt.lines, opt.selections) {
+                    (Some(_), true) => {
+                        return Err(anyhow::anyhow!(
+                            "--lines and --selections are mutually exclusive"
+                        )
+                        .into());
+                    }
+                    (Some(lines), false) => cmd::CatRange::Lines(lines),
+                    (None, true) => cmd::CatRange::Selections,
+                    (None, false) => cmd::CatRange::Buffer,
+                };
+                let split_by = if opt.zplit { '\0' } else { '\n' };
+                let res = cmd::cat(
+                    ctx,
+                    range,
+                    opt.numbered,
+                    opt.frame,
+                    split_by,
+                    to_buffer_ctx(opt.buffers),
+                )?;
                 write!(writer, "{res}")?;
             }
             _ => unreachable!(),
@@ -146,6 +448,19 @@ impl Dispatcher for SubCommand {
     }
 }
 
+// client selectors start with '@', e.g. '@last-focused'
+fn is_selector(client: &str) -> bool {
+    client.starts_with('@')
+}
+
+// commands which only read the session state
+fn is_query(command: &SubCommand) -> bool {
+    matches!(
+        command,
+        SubCommand::Ctx(_) | SubCommand::List(_) | SubCommand::Get(_) | SubCommand::Cat(_)
+    )
+}
+
 fn to_buffer_ctx(buffers: Vec<String>) -> Option<(String, i32)> {
     let mut iter = buffers.into_iter();
     let first = iter.next()?;
@@ -1,16 +1,48 @@
 mod attach;
+mod batch;
 mod cat;
+mod doctor;
 mod edit;
+mod fifo;
+mod filter;
 mod get;
 mod init;
 mod list;
+mod project;
+mod put;
+mod resolve;
+mod selection;
+mod set;
+mod start;
+mod watch;
 
 use super::context::*;
 use super::{Error, Result};
+use std::time::Duration;
+
+// used unless --timeout is given by commands which must not hang on a session
+// which never responds, as requests wait for as long as it takes by default
+
+// every running session is asked, so a stuck one must not hold the lookup up for long
+const PROJECT_TIMEOUT: Duration = Duration::from_secs(1);
+const DOCTOR_TIMEOUT: Duration = Duration::from_secs(3);
+// kakrc is sourced before the session is ready, which may take a while
+const START_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub(super) use attach::attach;
-pub(super) use cat::cat;
-pub(super) use edit::edit;
+pub(super) use batch::batch;
+pub(super) use cat::{Range as CatRange, cat};
+pub(super) use doctor::doctor;
+pub(super) use edit::{Client as EditClient, edit};
+pub(super) use fifo::fifo;
+pub(super) use filter::filter;
 pub(super) use get::*;
 pub(super) use init::init;
 pub(super) use list::*;
+pub(super) use project::project_session;
+pub(super) use put::{Mode as PutMode, put};
+pub(super) use resolve::{resolve_client, resolve_session, select_client};
+pub(super) use selection::selections;
+pub(super) use set::set;
+pub(super) use start::start;
+pub(super) use watch::watch;
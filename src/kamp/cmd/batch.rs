@@ -0,0 +1,148 @@
+use std::io::Write;
+
+use super::{Context, QueryKeyVal, Result, lex};
+use crate::argv::get::{Format, Key};
+use crate::kamp::json;
+
+/// Results of a batch query for a single buffer or client
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Batch(Vec<(String, Vec<String>)>);
+
+impl Batch {
+    /// Writes results according to format:
+    ///
+    /// plain: `<key>\t<value>` per value, a key of type list may appear several times
+    ///
+    /// json: `{"<key>":["<value>",...],...}` on a single line
+    pub fn write_to<W: Write>(&self, mut writer: W, format: &Format, split_by: char) -> Result<()> {
+        match format {
+            Format::Plain => {
+                for (key, values) in self.0.iter() {
+                    for value in values {
+                        write!(writer, "{key}\t{value}{split_by}")?;
+                    }
+                }
+            }
+            Format::Json => {
+                let mut buf = String::from('{');
+                for (i, (key, values)) in self.0.iter().enumerate() {
+                    if i != 0 {
+                        buf.push(',');
+                    }
+                    json::write_str(&mut buf, key)?;
+                    buf.push_str(":[");
+                    for (i, value) in values.iter().enumerate() {
+                        if i != 0 {
+                            buf.push(',');
+                        }
+                        json::write_str(&mut buf, value)?;
+                    }
+                    buf.push(']');
+                }
+                buf.push('}');
+                writeln!(writer, "{buf}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Key> for QueryKeyVal {
+    fn from(value: Key) -> Self {
+        match value {
+            Key::Val(name) => QueryKeyVal::Val(name),
+            Key::Opt(name) => QueryKeyVal::Opt(name),
+            Key::Reg(name) => QueryKeyVal::Reg(name),
+        }
+    }
+}
+
+/// Queries all keys in a single request, one result per buffer in context.
+pub(crate) fn batch(
+    ctx: &Context,
+    keys: Vec<Key>,
+    buffer_ctx: Option<(String, i32)>,
+) -> Result<Vec<Batch>> {
+    // send separates output of several buffers with an empty line
+    let separated = matches!(buffer_ctx, Some((_, n)) if n != 1);
+    let names: Vec<String> = keys.iter().map(ToString::to_string).collect();
+    // a line per key, so that no value can be taken for a separator
+    let body = keys
+        .into_iter()
+        .map(|key| {
+            format!(
+                "echo -quoting kakoune -end-of-line -to-file %opt<kamp_out> {}",
+                QueryKeyVal::from(key)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let output = ctx.send(body, buffer_ctx)?;
+    parse(&output, &names, separated)
+}
+
+fn parse(output: &str, names: &[String], separated: bool) -> Result<Vec<Batch>> {
+    let err = || anyhow::anyhow!("unexpected batch output: {output:?}");
+    let lines = lex::split_lines(output).map_err(anyhow::Error::new)?;
+    let chunk = names.len() + usize::from(separated);
+    if names.is_empty() || lines.len() % chunk != 0 {
+        return Err(err().into());
+    }
+    lines
+        .chunks(chunk)
+        .map(|lines| {
+            let (values, separator) = lines.split_at(names.len());
+            if separator.iter().any(|s| !s.is_empty()) {
+                return Err(err().into());
+            }
+            Ok(Batch(names.iter().cloned().zip(values.to_vec()).collect()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse() -> Result<()> {
+        let names = vec!["val:buffile".to_owned(), "opt:ft".to_owned()];
+        assert_eq!(
+            parse("'/a b'\n'rust'\n", &names, false)?,
+            vec![Batch(vec![
+                ("val:buffile".into(), vec!["/a b".into()]),
+                ("opt:ft".into(), vec!["rust".into()]),
+            ])]
+        );
+        assert_eq!(
+            parse("\n'a' 'b\n'\n\n'c'\n'<<KEY0>>'\n\n", &names, true)?,
+            vec![
+                Batch(vec![
+                    ("val:buffile".into(), vec![]),
+                    ("opt:ft".into(), vec!["a".into(), "b\n".into()]),
+                ]),
+                Batch(vec![
+                    ("val:buffile".into(), vec!["c".into()]),
+                    ("opt:ft".into(), vec!["<<KEY0>>".into()]),
+                ]),
+            ]
+        );
+        assert!(parse("'a'\n", &names, false).is_err());
+        assert!(parse("'a'\n'b'\n'c'\n", &names, true).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_write_to() -> Result<()> {
+        let batch = Batch(vec![
+            ("val:a".into(), vec!["1".into(), "2".into()]),
+            ("opt:b".into(), vec![]),
+        ]);
+        let mut buf = Vec::new();
+        batch.write_to(&mut buf, &Format::Plain, '\n')?;
+        batch.write_to(&mut buf, &Format::Json, '\n')?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "val:a\t1\nval:a\t2\n{\"val:a\":[\"1\",\"2\"],\"opt:b\":[]}\n"
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,240 @@
+use std::fmt::Write;
+
 use super::Context;
-use super::{Error, Result};
+use super::{Error, Result, lex};
+use crate::argv::cat::{Frame, LineRange};
+use crate::kamp::json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Range {
+    Buffer,
+    Lines(LineRange),
+    Selections,
+}
 
-pub(crate) fn cat(ctx: Context, buffer_ctx: Option<(String, i32)>) -> Result<String> {
+#[derive(Debug, PartialEq, Eq)]
+struct Region {
+    bufname: String,
+    start: usize,
+    text: String,
+}
+
+pub(crate) fn cat(
+    ctx: Context,
+    range: Range,
+    numbered: bool,
+    frame: Option<Frame>,
+    split_by: char,
+    buffer_ctx: Option<(String, i32)>,
+) -> Result<String> {
     if ctx.is_draft() && buffer_ctx.is_none() {
         return Err(Error::InvalidContext("either client or buffer is required"));
     }
-    ctx.send("write %opt{kamp_out}", buffer_ctx)
+    if range == Range::Buffer && !numbered && frame.is_none() {
+        return ctx.send("write %opt{kamp_out}", buffer_ctx);
+    }
+    let regions: Vec<Region> = match range {
+        Range::Buffer | Range::Lines(_) => {
+            let (keys, start) = match range {
+                Range::Lines(lines) => (keys(lines), lines.start.unwrap_or(1)),
+                _ => (String::from("%"), 1),
+            };
+            let body = format!(
+                "evaluate-commands -draft %{{ execute-keys '{keys}'; echo -quoting kakoune -to-file %opt<kamp_out> %val<bufname> %val<buf_line_count> %val<selection_desc> %val<selection> }}"
+            );
+            let output = ctx.send(body, buffer_ctx)?;
+            line_regions(&output, start)?
+        }
+        Range::Selections => {
+            if matches!(buffer_ctx, Some((_, n)) if n != 1) {
+                return Err(Error::InvalidContext("single buffer is required"));
+            }
+            let output = ctx.send(
+                "echo -quoting kakoune -to-file %opt<kamp_out> %val<bufname> %val<selections_desc> %val<selections>",
+                buffer_ctx,
+            )?;
+            let words = lex::split(&output).map_err(anyhow::Error::new)?;
+            let Some((bufname, words)) = words.split_first().filter(|(_, w)| w.len() % 2 == 0)
+            else {
+                return Err(anyhow::anyhow!("unexpected cat output: {output:?}").into());
+            };
+            let (desc, text) = words.split_at(words.len() / 2);
+            desc.iter()
+                .zip(text)
+                .map(|(desc, text)| {
+                    Ok(Region {
+                        bufname: bufname.clone(),
+                        start: start_line(desc)?,
+                        text: text.clone(),
+                    })
+                })
+                .collect::<Result<_>>()?
+        }
+    };
+    render(&regions, range, numbered, frame, split_by)
+}
+
+// parses bufname, line count, desc, text quadruples of single selection per buffer,
+// buffers shorter than start line have no region, as kakoune clamps 'g' to the last line
+fn line_regions(output: &str, start: usize) -> Result<Vec<Region>> {
+    let words = lex::split(output).map_err(anyhow::Error::new)?;
+    let mut regions = Vec::new();
+    for quadruple in words.chunks(4) {
+        let [bufname, line_count, desc, text] = quadruple else {
+            return Err(anyhow::anyhow!("unexpected cat output: {output:?}").into());
+        };
+        let line_count: usize = line_count.parse().map_err(anyhow::Error::new)?;
+        if start > line_count {
+            continue;
+        }
+        regions.push(Region {
+            bufname: bufname.clone(),
+            start: start_line(desc)?,
+            text: text.clone(),
+        });
+    }
+    Ok(regions)
+}
+
+fn render(
+    regions: &[Region],
+    range: Range,
+    numbered: bool,
+    frame: Option<Frame>,
+    split_by: char,
+) -> Result<String> {
+    let mut buf = String::new();
+    let mut last = None;
+    for region in regions {
+        let text = if numbered {
+            let mut buf = String::with_capacity(region.text.len());
+            for (i, line) in region.text.split_inclusive('\n').enumerate() {
+                write!(buf, "{:>6}\t{line}", region.start + i)?;
+            }
+            buf
+        } else {
+            region.text.clone()
+        };
+        match frame {
+            Some(Frame::Nul) => {
+                write!(buf, "{}\0{text}\0", region.bufname)?;
+            }
+            Some(Frame::Json) => {
+                buf.push_str("{\"bufname\":");
+                json::write_str(&mut buf, &region.bufname)?;
+                write!(buf, ",\"line\":{},\"text\":", region.start)?;
+                json::write_str(&mut buf, &text)?;
+                buf.push_str("}\n");
+            }
+            Some(Frame::Header) | None => {
+                if frame.is_some() && last != Some(&region.bufname) {
+                    if !buf.is_empty() && !buf.ends_with('\n') {
+                        buf.push('\n');
+                    }
+                    writeln!(buf, "==> {} <==", region.bufname)?;
+                }
+                buf.push_str(&text);
+                // line wise selections are newline terminated already
+                if range == Range::Selections && !(split_by == '\n' && text.ends_with('\n')) {
+                    buf.push(split_by);
+                }
+            }
+        }
+        last = Some(&region.bufname);
+    }
+    Ok(buf)
+}
+
+// keys to select full lines of range, counts are clamped by kakoune,
+// so start past the end is told apart by line count
+fn keys(lines: LineRange) -> String {
+    let start = lines.start.unwrap_or(1);
+    match lines.end {
+        None => format!("{start}gGex"),
+        Some(end) if end > start => format!("{start}g{}Jx", end - start),
+        Some(_) => format!("{start}gx"),
+    }
+}
+
+// first line of selection described as <anchor_line>.<anchor_column>,<cursor_line>.<cursor_column>
+fn start_line(desc: &str) -> Result<usize> {
+    let line = |coord: &str| {
+        coord
+            .split('.')
+            .next()
+            .and_then(|n| n.parse::<usize>().ok())
+    };
+    desc.split_once(',')
+        .and_then(|(anchor, cursor)| Some(line(anchor)?.min(line(cursor)?)))
+        .ok_or_else(|| anyhow::anyhow!("invalid selection desc: {desc:?}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_keys() {
+        let range = |start, end| LineRange { start, end };
+        assert_eq!(keys(range(None, None)), "1gGex");
+        assert_eq!(keys(range(Some(100), None)), "100gGex");
+        assert_eq!(keys(range(None, Some(10))), "1g9Jx");
+        assert_eq!(keys(range(Some(100), Some(200))), "100g100Jx");
+        assert_eq!(keys(range(Some(5), Some(5))), "5gx");
+    }
+    #[test]
+    fn test_line_regions() -> Result<()> {
+        let output = "'a' '3' '2.1,3.2' 'x\ny\n' 'b' '1' '1.1,1.1' 'z\n'";
+        assert_eq!(
+            line_regions(output, 2)?,
+            vec![Region {
+                bufname: "a".into(),
+                start: 2,
+                text: "x\ny\n".into(),
+            }]
+        );
+        assert_eq!(line_regions(output, 1)?.len(), 2);
+        assert!(line_regions(output, 4)?.is_empty());
+        assert!(line_regions("'a' '3' '2.1,3.2'", 1).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_start_line() -> Result<()> {
+        assert_eq!(start_line("3.1,5.2")?, 3);
+        assert_eq!(start_line("5.2,3.1")?, 3);
+        assert!(start_line("5.2").is_err());
+        assert!(start_line("a.2,1.1").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_render() -> Result<()> {
+        let region = |bufname: &str, start, text: &str| Region {
+            bufname: bufname.into(),
+            start,
+            text: text.into(),
+        };
+        let regions = [region("a", 1, "x\ny"), region("b", 3, "z\n")];
+        let buffer = Range::Buffer;
+        assert_eq!(
+            render(&regions, buffer, false, Some(Frame::Header), '\n')?,
+            "==> a <==\nx\ny\n==> b <==\nz\n"
+        );
+        assert_eq!(
+            render(&regions, buffer, true, Some(Frame::Nul), '\n')?,
+            "a\0     1\tx\n     2\ty\0b\0     3\tz\n\0"
+        );
+        assert_eq!(
+            render(&regions, buffer, false, Some(Frame::Json), '\n')?,
+            "{\"bufname\":\"a\",\"line\":1,\"text\":\"x\\ny\"}\n{\"bufname\":\"b\",\"line\":3,\"text\":\"z\\n\"}\n"
+        );
+        let selections = [region("a", 1, "x"), region("a", 2, "y\n")];
+        assert_eq!(
+            render(&selections, Range::Selections, false, None, '\n')?,
+            "x\ny\n"
+        );
+        assert_eq!(
+            render(&selections, Range::Selections, false, None, '\0')?,
+            "x\0y\n\0"
+        );
+        Ok(())
+    }
 }
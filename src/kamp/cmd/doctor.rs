@@ -0,0 +1,249 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{
+    Context, DOCTOR_TIMEOUT, Error, QueryContext, QueryKeyVal, Result, resolve_client,
+    select_client,
+};
+use crate::kamp::kak;
+
+#[derive(Clone, Copy)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+    Skip,
+}
+
+struct Report<W> {
+    writer: W,
+    failed: usize,
+}
+
+impl<W: Write> Report<W> {
+    fn check(&mut self, status: Status, name: &str, msg: &str, hint: &str) -> Result<bool> {
+        let tag = match status {
+            Status::Ok => " ok ",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+            Status::Skip => "skip",
+        };
+        writeln!(self.writer, "[{tag}] {name}: {msg}")?;
+        if matches!(status, Status::Warn | Status::Fail) && !hint.is_empty() {
+            writeln!(self.writer, "       hint: {hint}")?;
+        }
+        if let Status::Fail = status {
+            self.failed += 1;
+        }
+        Ok(matches!(status, Status::Ok | Status::Warn))
+    }
+}
+
+pub(crate) fn doctor<W: Write>(
+    session: Option<String>,
+    client: Option<String>,
+    timeout: Option<Duration>,
+    debug: bool,
+    writer: W,
+) -> Result<()> {
+    let mut r = Report { writer, failed: 0 };
+
+    let kak_ok = match find_in_path("kak") {
+        Some(path) => r.check(Status::Ok, "kak", &path.display().to_string(), "")?,
+        None => r.check(
+            Status::Fail,
+            "kak",
+            "not found in PATH",
+            "install kakoune or add its bin directory to PATH",
+        )?,
+    };
+
+    let session = match session {
+        Some(session) => {
+            r.check(Status::Ok, "session", &session, "")?;
+            Some(session)
+        }
+        None => {
+            r.check(
+                Status::Fail,
+                "session",
+                "not set",
+                "pass -s <session> or run from a shell started by kamp-connect (KAKOUNE_SESSION)",
+            )?;
+            None
+        }
+    };
+
+    let listed = match (&session, kak_ok) {
+        (Some(session), true) => match list_sessions() {
+            Ok(sessions) if sessions.lines().any(|s| s == session) => {
+                r.check(Status::Ok, "kak -l", "session is running", "")?
+            }
+            Ok(_) => r.check(
+                Status::Fail,
+                "kak -l",
+                "session is not running",
+                "start one with 'kak -s <session>' or pick one of 'kak -l'",
+            )?,
+            Err(e) => r.check(Status::Fail, "kak -l", &e.to_string(), "")?,
+        },
+        _ => r.check(Status::Skip, "kak -l", "no session or kak", "")?,
+    };
+
+    let Some(session) = session.filter(|_| listed) else {
+        r.check(Status::Skip, "socket", "session is not running", "")?;
+        r.check(Status::Skip, "response", "session is not running", "")?;
+        r.check(Status::Skip, "kamp init", "session is not running", "")?;
+        r.check(Status::Skip, "TMPDIR", "session is not running", "")?;
+        r.check(Status::Skip, "client", "session is not running", "")?;
+        return finish(r);
+    };
+
+    let socket = kak::session_path(&session);
+    if socket.exists() {
+        r.check(Status::Ok, "socket", &socket.display().to_string(), "")?;
+    } else {
+        r.check(
+            Status::Warn,
+            "socket",
+            &format!(
+                "{} does not exist, falling back to kak -p",
+                socket.display()
+            ),
+            "make sure XDG_RUNTIME_DIR and TMPDIR are the same as kakoune's",
+        )?;
+    }
+
+    let mut ctx = Context::new(&session, debug);
+    ctx.set_timeout(timeout.or(Some(DOCTOR_TIMEOUT)));
+
+    let query = |name: &str| {
+        QueryContext::new(
+            QueryKeyVal::Val(name.into()),
+            Default::default(),
+            Default::default(),
+            false,
+        )
+    };
+
+    let clients = match ctx.query_kak(query("client_list"), None) {
+        Ok(clients) => {
+            r.check(Status::Ok, "response", "session responds", "")?;
+            clients
+        }
+        Err(e) => {
+            r.check(
+                Status::Fail,
+                "response",
+                &e.to_string(),
+                "session may be busy, e.g. waiting in a prompt or running a shell command",
+            )?;
+            r.check(Status::Skip, "kamp init", "session does not respond", "")?;
+            r.check(Status::Skip, "TMPDIR", "session does not respond", "")?;
+            r.check(Status::Skip, "client", "session does not respond", "")?;
+            return finish(r);
+        }
+    };
+
+    let grep_query = QueryContext::new(
+        QueryKeyVal::Opt("kamp_grep_query".into()),
+        Default::default(),
+        Default::default(),
+        false,
+    );
+    match ctx.query_kak(grep_query, None) {
+        Ok(_) => r.check(Status::Ok, "kamp init", "kamp-init has run", "")?,
+        Err(Error::KakEvalCatch { error, .. }) => r.check(
+            Status::Fail,
+            "kamp init",
+            error.trim(),
+            "add 'evaluate-commands %sh{ kamp init -a }' to kakrc and restart the session",
+        )?,
+        Err(e) => r.check(Status::Fail, "kamp init", &e.to_string(), "")?,
+    };
+
+    // responses don't depend on it, but 'kak -l' and socket fallback may
+    let tmpdir = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".into());
+    let kak_tmpdir = ctx.query_kak(
+        QueryContext::new_sh(vec![r#"printf %s "${TMPDIR:-/tmp}""#.into()], true),
+        None,
+    );
+    match kak_tmpdir.map(|mut v| v.pop().unwrap_or_default()) {
+        Ok(kak_tmpdir) if kak_tmpdir == tmpdir => r.check(Status::Ok, "TMPDIR", &tmpdir, "")?,
+        Ok(kak_tmpdir) => r.check(
+            Status::Warn,
+            "TMPDIR",
+            &format!("kakoune has {kak_tmpdir:?}, this shell has {tmpdir:?}"),
+            "export the same TMPDIR for the editor and the shell if sessions are not found",
+        )?,
+        Err(e) => r.check(Status::Fail, "TMPDIR", &e.to_string(), "")?,
+    };
+
+    // resolved the same way as for any other command
+    let client = match client {
+        Some(client) => {
+            let resolved = match client.strip_prefix('@') {
+                Some(selector) => select_client(&ctx, selector),
+                None => resolve_client(&ctx, client.clone()),
+            };
+            match resolved {
+                Ok(client) => Some(client),
+                Err(e) => {
+                    r.check(
+                        Status::Fail,
+                        "client",
+                        &format!("{client}: {e}"),
+                        "check the name or selector, or pass -c <client>",
+                    )?;
+                    return finish(r);
+                }
+            }
+        }
+        None => None,
+    };
+    match client {
+        Some(client) if clients.contains(&client) => {
+            r.check(Status::Ok, "client", &client, "")?;
+        }
+        Some(client) => {
+            r.check(
+                Status::Fail,
+                "client",
+                &format!("{client} is not one of {clients:?}"),
+                "pass -c <client> or run from a shell started by kamp-connect (KAKOUNE_CLIENT)",
+            )?;
+        }
+        None => {
+            r.check(
+                Status::Skip,
+                "client",
+                "not set, commands run in draft context",
+                "",
+            )?;
+        }
+    }
+
+    finish(r)
+}
+
+fn finish<W: Write>(r: Report<W>) -> Result<()> {
+    if r.failed != 0 {
+        return Err(Error::CheckFailed(r.failed));
+    }
+    Ok(())
+}
+
+fn list_sessions() -> Result<String> {
+    Ok(String::from_utf8(kak::list_sessions()?)?)
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| {
+            path.metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+}
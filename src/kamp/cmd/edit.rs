@@ -1,36 +1,64 @@
-use std::{borrow::Cow, path::Path};
+use std::fmt::Write;
+use std::path::Path;
 
-use super::{Context, Error, Result};
+use super::watch::Watcher;
+use super::{Context, Error, Result, lex};
+use crate::argv::edit::Split;
+use crate::kamp::context::command::with_register;
 
-pub(crate) fn edit(ctx: Context, new: bool, focus: bool, files: Vec<String>) -> Result<bool> {
+/// Client to open files in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Client {
+    Context,
+    New,
+    /// new client created by the session's terminal integration
+    Terminal(Option<Split>),
+}
+
+// windowing modules shipped with kakoune which can split
+const SPLIT_MODULES: [&str; 4] = ["tmux", "wezterm", "zellij", "screen"];
+
+pub(crate) fn edit(
+    ctx: Context,
+    client: Client,
+    focus: bool,
+    wait: bool,
+    files: Vec<String>,
+) -> Result<bool> {
     let mut buf = String::new();
-    let mut pair = [None; 2];
+    let mut paths = Vec::new();
+    let mut targets = Vec::new();
     let mut coord = None;
-    let mut iter = files.iter();
 
-    for (i, item) in iter.by_ref().take(2).enumerate() {
-        if Path::new(item).is_file() || !item.starts_with('+') {
-            pair[1 - i] = Some(item);
+    for item in &files {
+        if item.starts_with('+') && !Path::new(item).is_file() {
+            if coord.is_some() {
+                return Err(Error::UnexpectedCoordPosition(item.clone()));
+            }
+            coord = Some(parse(item)?);
             continue;
         }
-        if coord.is_some() {
-            return Err(Error::UnexpectedCoordPosition(item.clone()));
-        }
-        coord = Some(parse(item)?);
+        let (path, pos) = match split_position(item) {
+            Some((path, pos)) if !Path::new(item).exists() => (path, Some(pos)),
+            _ => (item.as_str(), None),
+        };
+        // '+line' applies to the following file
+        targets.push((path, coord.take().or(pos)));
+    }
+    // and to the preceding one if it is the last argument
+    if let (Some(coord), Some((_, pos))) = (coord, targets.last_mut()) {
+        *pos = Some(coord);
     }
 
-    for (i, item) in iter.rev().chain(pair.into_iter().flatten()).enumerate() {
-        let path = {
-            let path = Path::new(item);
-            if path.is_relative() {
-                Cow::Owned(path.canonicalize()?)
-            } else {
-                Cow::Borrowed(path)
-            }
-        };
+    // first file is opened last to be displayed
+    for (i, (item, pos)) in targets.into_iter().rev().enumerate() {
+        // resolved the way kakoune resolves buffile, so that --wait can match it on close,
+        // files which don't exist yet can't be resolved
+        let path = std::fs::canonicalize(item)
+            .or_else(|_| std::env::current_dir().map(|dir| dir.join(item)))?;
         let path = path
             .to_str()
-            .expect("expected valid path to str conversion");
+            .ok_or_else(|| anyhow::anyhow!("path is not valid utf8: {}", path.display()))?;
         if i != 0 {
             buf.push('\n');
         }
@@ -40,44 +68,182 @@ pub(crate) fn edit(ctx: Context, new: bool, focus: bool, files: Vec<String>) ->
         } else {
             buf.push_str(path);
         }
+        if let Some(pos) = pos {
+            pos.write_to(&mut buf)?;
+        }
+        paths.push(path.to_owned());
     }
 
-    let scratch = if buf.is_empty() {
+    let scratch = buf.is_empty();
+    if scratch {
         buf.push_str("edit -scratch");
-        true
-    } else {
-        if let Some(coord) = coord {
-            for n in coord {
-                buf.push(' ');
-                buf.push_str(&n.to_string());
-            }
-        }
-        false
-    };
+    }
 
-    if new || ctx.is_draft() {
+    if let Client::Terminal(split) = client {
+        let args = ["kak", "-c", &ctx.session(), "-e", &buf]
+            .map(lex::quote)
+            .join(" ");
+        ctx.send(with_register(&args, &terminal(split)), None)
+            .map(|_| scratch)
+    } else if client == Client::New || ctx.is_draft() {
         ctx.connect(buf).map(|_| scratch)
     } else {
         if focus {
             buf.push_str("\nfocus");
         }
-        ctx.send(buf, None).map(|_| scratch)
+        if !wait {
+            return ctx.send(buf, None).map(|_| scratch);
+        }
+        let client = ctx.client().expect("client in context");
+        let mut watcher = Watcher::new(&ctx)?;
+        // hooks go in the same request, so that closing can't be missed
+        buf.push('\n');
+        buf.push_str(&watcher.hook(
+            "BufClose",
+            ".*",
+            ["BufClose", "%val<buffile>", "%val<modified>", "''"],
+        )?);
+        buf.push('\n');
+        buf.push_str(&watcher.hook(
+            "ClientClose",
+            &format!("\\A\\Q{client}\\E\\z"),
+            ["ClientClose", "''", "''", "''"],
+        )?);
+        let res = ctx
+            .send(buf, None)
+            .and_then(|_| wait_closed(&ctx, &mut watcher, paths));
+        let removed = ctx.send(watcher.remove(), None);
+        res?;
+        removed.map(|_| scratch)
+    }
+}
+
+// command to run 'kak' arguments in dquote register in a new terminal,
+// splits are tried with every windowing module, as kakoune has no generic command for them
+fn terminal(split: Option<Split>) -> String {
+    let Some(split) = split else {
+        return String::from("terminal %reg{dquote}");
+    };
+    let split = match split {
+        Split::Horizontal => "horizontal",
+        Split::Vertical => "vertical",
+    };
+    SPLIT_MODULES.iter().rev().fold(
+        String::from("fail 'no terminal integration supports splits'"),
+        |fallback, module| {
+            format!("try %{{ {module}-terminal-{split} %reg{{dquote}} }} catch %{{ {fallback} }}")
+        },
+    )
+}
+
+// blocks until every buffer in paths is closed or the client quits,
+// buffers left modified are reported as not saved
+fn wait_closed(ctx: &Context, watcher: &mut Watcher, mut paths: Vec<String>) -> Result<()> {
+    let mut unsaved = Vec::new();
+    'wait: while !paths.is_empty() {
+        let Some(records) = watcher.next()? else {
+            return Err(anyhow::anyhow!("interrupted").into());
+        };
+        for [hook, buffile, modified, _] in records {
+            if hook == "ClientClose" {
+                break 'wait;
+            }
+            if let Some(i) = paths.iter().position(|p| *p == buffile) {
+                paths.swap_remove(i);
+                if modified == "true" {
+                    unsaved.push(buffile);
+                }
+            }
+        }
+    }
+    for path in paths {
+        let modified = ctx.send(
+            "echo -to-file %opt{kamp_out} %val{modified}",
+            Some((lex::quote(&path), 1)),
+        );
+        // buffer could be already gone
+        if modified.is_ok_and(|m| m == "true") {
+            unsaved.push(path);
+        }
+    }
+    if unsaved.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::NotSaved(unsaved.join(" ")))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Position {
+    line: i32,
+    column: Option<i32>,
+    end: Option<(i32, i32)>,
+}
+
+impl Position {
+    // appends arguments of 'edit' command, followed by 'select' command if position is a range
+    fn write_to(&self, buf: &mut String) -> std::fmt::Result {
+        write!(buf, " {}", self.line)?;
+        if let Some(column) = self.column {
+            write!(buf, " {column}")?;
+        }
+        if let Some((line, column)) = self.end {
+            let start = self.column.unwrap_or(1);
+            write!(buf, "\nselect {}.{start},{line}.{column}", self.line)?;
+        }
+        Ok(())
     }
 }
 
 // assuming coord starts with '+'
-fn parse(coord: &str) -> Result<Vec<i32>> {
-    // parsing first value as '+n' so '+:<n>' will fail
-    coord
-        .splitn(2, ':')
-        .take_while(|&s| !s.is_empty()) // make sure '+n:' is valid
-        .map(|s| {
-            s.parse().map_err(|e| Error::InvalidCoordinates {
-                coord: String::from(coord),
-                source: anyhow::Error::new(e),
-            })
-        })
-        .collect()
+fn parse(coord: &str) -> Result<Position> {
+    let err = |e| Error::InvalidCoordinates {
+        coord: String::from(coord),
+        source: anyhow::Error::new(e),
+    };
+    // parsing line as '+n' so '+:<n>' will fail
+    let (line, column) = match coord.split_once(':') {
+        Some((line, column)) => (line, Some(column).filter(|s| !s.is_empty())), // make sure '+n:' is valid
+        None => (coord, None),
+    };
+    Ok(Position {
+        line: line.parse().map_err(err)?,
+        column: column.map(str::parse).transpose().map_err(err)?,
+        end: None,
+    })
+}
+
+// splits 'file:line[:col][-line:col]' as emitted by compilers and grep tools,
+// trailing ':' is allowed
+fn split_position(item: &str) -> Option<(&str, Position)> {
+    let num = |s: &str| {
+        s.bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| s.parse().ok())
+            .flatten()
+    };
+    let position = |spec: &str| {
+        let spec = spec.strip_suffix(':').unwrap_or(spec);
+        let (start, end) = match spec.split_once('-') {
+            Some((start, end)) => (start, Some(end)),
+            None => (spec, None),
+        };
+        let (line, column) = match start.split_once(':') {
+            Some((line, column)) => (num(line)?, Some(num(column)?)),
+            None => (num(start)?, None),
+        };
+        let end = match end {
+            Some(end) => {
+                let (line, column) = end.split_once(':')?;
+                Some((num(line)?, num(column)?))
+            }
+            None => None,
+        };
+        Some(Position { line, column, end })
+    };
+    item.match_indices(':')
+        .filter(|&(i, _)| i != 0)
+        .find_map(|(i, _)| position(&item[i + 1..]).map(|pos| (&item[..i], pos)))
 }
 
 #[cfg(test)]
@@ -85,9 +251,14 @@ mod tests {
     use super::*;
     #[test]
     fn test_parse_ok() -> Result<()> {
-        assert_eq!(parse("+1")?, vec![1]);
-        assert_eq!(parse("+1:")?, vec![1]);
-        assert_eq!(parse("+1:1")?, vec![1, 1]);
+        let pos = |line, column| Position {
+            line,
+            column,
+            end: None,
+        };
+        assert_eq!(parse("+1")?, pos(1, None));
+        assert_eq!(parse("+1:")?, pos(1, None));
+        assert_eq!(parse("+1:1")?, pos(1, Some(1)));
         Ok(())
     }
     #[test]
@@ -106,4 +277,47 @@ mod tests {
         assert!(parse("+a:1").is_err());
         assert!(parse("+1:a").is_err());
     }
+    #[test]
+    fn test_split_position() {
+        let pos = |line, column, end| Position { line, column, end };
+        assert_eq!(
+            split_position("a.rs:12"),
+            Some(("a.rs", pos(12, None, None)))
+        );
+        assert_eq!(
+            split_position("a.rs:12:5:"),
+            Some(("a.rs", pos(12, Some(5), None)))
+        );
+        assert_eq!(
+            split_position("a:b.rs:12:5-14:2"),
+            Some(("a:b.rs", pos(12, Some(5), Some((14, 2)))))
+        );
+        assert_eq!(
+            split_position("a.rs:12-14:2"),
+            Some(("a.rs", pos(12, None, Some((14, 2)))))
+        );
+        assert_eq!(split_position("a.rs"), None);
+        assert_eq!(split_position(":12"), None);
+        assert_eq!(split_position("a.rs:"), None);
+        assert_eq!(split_position("a.rs:+1"), None);
+        assert_eq!(split_position("a.rs:12-14"), None);
+        assert_eq!(split_position("a.rs:12:x"), None);
+    }
+    #[test]
+    fn test_position_write_to() -> Result<()> {
+        let mut buf = String::new();
+        split_position("a:1").unwrap().1.write_to(&mut buf)?;
+        split_position("a:1:2").unwrap().1.write_to(&mut buf)?;
+        split_position("a:1-3:4").unwrap().1.write_to(&mut buf)?;
+        assert_eq!(buf, " 1 1 2 1\nselect 1.1,3.4");
+        Ok(())
+    }
+    #[test]
+    fn test_terminal() {
+        assert_eq!(terminal(None), "terminal %reg{dquote}");
+        assert_eq!(
+            terminal(Some(Split::Vertical)),
+            "try %{ tmux-terminal-vertical %reg{dquote} } catch %{ try %{ wezterm-terminal-vertical %reg{dquote} } catch %{ try %{ zellij-terminal-vertical %reg{dquote} } catch %{ try %{ screen-terminal-vertical %reg{dquote} } catch %{ fail 'no terminal integration supports splits' } } } }"
+        );
+    }
 }
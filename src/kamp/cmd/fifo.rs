@@ -0,0 +1,84 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+
+use super::{Context, Error, Result, lex};
+use crate::kamp::context::command::append_command;
+use crate::kamp::context::fifo::{Fifo, TempFile};
+
+// upper bound of text sent at once in append mode
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub(crate) fn fifo<R: Read>(
+    ctx: &Context,
+    name: &str,
+    scroll: bool,
+    readonly: bool,
+    append: bool,
+    input: R,
+) -> Result<()> {
+    if ctx.is_draft() {
+        return Err(Error::InvalidContext("client is required"));
+    }
+    let name = lex::quote(&format!("*{name}*"));
+    if append {
+        if scroll {
+            return Err(anyhow::anyhow!("--scroll is not supported with --append").into());
+        }
+        return append_stream(ctx, name, readonly, input);
+    }
+
+    let fifo = Fifo::new(&ctx.session())?;
+    fifo.remove_on_signal()?;
+    let path = fifo
+        .path()
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("fifo path is not valid utf8: {}", fifo.path().display()))?;
+
+    let mut cmd = String::from("edit");
+    if scroll {
+        cmd.push_str(" -scroll");
+    }
+    if readonly {
+        cmd.push_str(" -readonly");
+    }
+    cmd.push_str(" -fifo ");
+    cmd.push_str(&lex::quote(path));
+    cmd.push(' ');
+    cmd.push_str(&name);
+    cmd.push_str("\nfocus");
+    ctx.send(cmd, None)?;
+
+    // blocks until kakoune opens the fifo for reading, which it does on edit
+    let mut f = std::fs::OpenOptions::new().write(true).open(fifo.path())?;
+    let mut input = input;
+    match std::io::copy(&mut input, &mut f) {
+        // buffer has been deleted
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        res => res.map(drop).map_err(From::from),
+    }
+}
+
+fn append_stream<R: Read>(ctx: &Context, name: String, readonly: bool, input: R) -> Result<()> {
+    let mut cmd = format!("try %{{ buffer {name} }} catch %{{ edit -scratch {name} }}");
+    if readonly {
+        cmd.push_str("\nset-option buffer readonly true");
+    }
+    cmd.push_str("\nfocus");
+    ctx.send(cmd, None)?;
+
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, input);
+    // bytes rather than text, so that any input taken by the fifo is taken here as well
+    let mut chunk = Vec::new();
+    loop {
+        // take whatever is buffered already, so a fast producer needs fewer round-trips
+        while reader.read_until(b'\n', &mut chunk)? != 0
+            && !reader.buffer().is_empty()
+            && chunk.len() < CHUNK_SIZE
+        {}
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        let tmp = TempFile::with_data(&ctx.session(), &chunk)?;
+        ctx.send(append_command(&tmp.expansion()?), Some((name.clone(), 1)))?;
+        chunk.clear();
+    }
+}
@@ -0,0 +1,125 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::{Context, Error, Result, lex};
+use crate::kamp::context::fifo::TempFile;
+
+/// Pipes each selection, or the whole buffer in buffer context, through command
+/// run by kamp itself and replaces it with the output in a single undo step.
+pub(crate) fn filter(
+    ctx: &Context,
+    command: Vec<String>,
+    buffer_ctx: Option<(String, i32)>,
+) -> Result<()> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(Error::CommandRequired);
+    };
+    let (query, state, action) = match &buffer_ctx {
+        Some((_, 1)) => (
+            "evaluate-commands -draft %{ execute-keys '%'; echo -quoting kakoune -to-file %opt<kamp_out> %val<timestamp> %val<selections> }",
+            "$kak_timestamp",
+            "execute-keys -draft '%R'",
+        ),
+        Some(_) => return Err(Error::InvalidContext("single buffer is required")),
+        None if ctx.is_draft() => {
+            return Err(Error::InvalidContext("either client or buffer is required"));
+        }
+        None => (
+            "echo -quoting kakoune -to-file %opt<kamp_out> %val<timestamp> %val<selections_desc> %val<selections>",
+            "$kak_timestamp $kak_selections_desc",
+            "execute-keys R",
+        ),
+    };
+
+    let output = ctx.send(query, buffer_ctx.clone())?;
+    let (expected, inputs) = parse(&output, buffer_ctx.is_none())?;
+    let mut values = String::from("set-register dquote");
+    for input in inputs {
+        values.push(' ');
+        values.push_str(&lex::quote(&run(program, args, input)?));
+    }
+    let tmp = TempFile::with_data(&ctx.session(), values.as_bytes())?;
+    let body = format!(
+        "evaluate-commands -save-regs '\"' %🦀\n{}\nevaluate-commands {}\n{action}\n🦀",
+        check(state, &expected),
+        tmp.expansion()?,
+    );
+    ctx.send(body, buffer_ctx).map(drop)
+}
+
+// splits output of query into the state to check before writing back and the selections,
+// which are preceded by as many descs in client context
+fn parse(output: &str, with_desc: bool) -> Result<(String, Vec<String>)> {
+    let err = || anyhow::anyhow!("unexpected filter output: {output:?}");
+    let mut words = lex::split(output).map_err(anyhow::Error::new)?;
+    if words.is_empty() || (with_desc && words.len() % 2 == 0) {
+        return Err(err().into());
+    }
+    let inputs = words.split_off(if with_desc { words.len() / 2 + 1 } else { 1 });
+    Ok((words.join(" "), inputs))
+}
+
+// command failing if the buffer or selections have changed since they were read,
+// state is made of numbers and punctuation only, so it is safe to quote
+fn check(state: &str, expected: &str) -> String {
+    format!(
+        "evaluate-commands %sh{{ [ \"{state}\" = '{expected}' ] || echo \"fail 'selections have changed while filtering'\" }}"
+    )
+}
+
+fn run(program: &str, args: &[String], input: String) -> Result<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // write on a separate thread, so a command producing output early doesn't deadlock
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    match writer.join().unwrap() {
+        // command may not read its input at all
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+        _ => {}
+    }
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("{program}: {}", output.status).into());
+    }
+    String::from_utf8(output.stdout).map_err(From::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse() -> Result<()> {
+        assert_eq!(
+            parse("'7' '1.1,1.2' '2.1,2.1' 'ab' 'c'", true)?,
+            ("7 1.1,1.2 2.1,2.1".into(), vec!["ab".into(), "c".into()])
+        );
+        assert_eq!(
+            parse("'7' 'ab\n'", false)?,
+            ("7".into(), vec!["ab\n".into()])
+        );
+        assert!(parse("'7' '1.1,1.2' 'ab' 'c'", true).is_err());
+        assert!(parse("", false).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_check() {
+        assert_eq!(
+            check("$kak_timestamp", "7"),
+            r#"evaluate-commands %sh{ [ "$kak_timestamp" = '7' ] || echo "fail 'selections have changed while filtering'" }"#
+        );
+    }
+    #[test]
+    fn test_run() -> Result<()> {
+        assert_eq!(
+            run("tr", &["a-z".into(), "A-Z".into()], "ab\n".into())?,
+            "AB\n"
+        );
+        assert_eq!(run("true", &[], "ab".into())?, "");
+        assert!(run("false", &[], "ab".into()).is_err());
+        Ok(())
+    }
+}
@@ -58,6 +58,9 @@ impl From<SubCommand> for QueryContext {
                 o.verbatim,
             ),
             SubCommand::Shell(o) => QueryContext::new_sh(o.command, o.verbatim),
+            SubCommand::Selection(_) | SubCommand::Batch(_) => {
+                unreachable!("not a single query")
+            }
         }
     }
 }
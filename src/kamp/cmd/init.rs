@@ -7,20 +7,12 @@ define-command -hidden -override kamp-init %{
     declare-option -hidden str kamp_grep_query
     declare-option -hidden str kamp_out
     declare-option -hidden str kamp_err
-    evaluate-commands %sh{
-        kamp_out="${TMPDIR:-/tmp}/kamp-${kak_session}.out"
-        kamp_err="${TMPDIR:-/tmp}/kamp-${kak_session}.err"
-        mkfifo "$kamp_out" "$kamp_err"
-        printf 'set global kamp_%s %s\n' out "$kamp_out" err "$kamp_err"
-    }
-}
-
-define-command -hidden -override kamp-end %{
-    nop %sh{ rm -f "$kak_opt_kamp_out" "$kak_opt_kamp_err" }
+    declare-option -hidden str kamp_focused_client
+    hook -group kamp global ClientCreate .* %{ set-option global kamp_focused_client %val{client} }
+    hook -group kamp global FocusIn .* %{ set-option global kamp_focused_client %val{client} }
 }
 
 hook global KakBegin .* kamp-init
-hook global KakEnd .* kamp-end
 "#;
 
 pub(crate) fn init(export: Vec<KeyValue>, alias: bool) -> Result<String> {
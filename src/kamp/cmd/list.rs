@@ -1,12 +1,15 @@
+use std::io::Write;
 use std::rc::Rc;
+use std::time::Duration;
 
 use super::QueryContext;
 use super::QueryKeyVal;
 
 use super::Context;
 use super::Result;
+use crate::argv::list::Format;
+use crate::kamp::json;
 
-#[allow(unused)]
 #[derive(Debug)]
 pub struct Session {
     name: Rc<Box<str>>,
@@ -18,9 +21,56 @@ impl Session {
     fn new(name: Rc<Box<str>>, pwd: String, clients: Vec<Client>) -> Self {
         Session { name, pwd, clients }
     }
+
+    /// Writes session according to format:
+    ///
+    /// json: one object per line
+    /// `{"session":"<name>","pwd":"<pwd>","clients":[{"client":"<name>","bufname":"<bufname>"}]}`
+    ///
+    /// tsv: one line per client `<session>\t<pwd>\t<client>\t<bufname>`,
+    /// client and bufname are empty if session has no clients
+    pub fn write_to<W: Write>(&self, mut writer: W, format: &Format) -> Result<()> {
+        match format {
+            Format::Debug => writeln!(writer, "{self:#?}")?,
+            Format::Json => {
+                let mut buf = String::from("{\"session\":");
+                json::write_str(&mut buf, &self.name)?;
+                buf.push_str(",\"pwd\":");
+                json::write_str(&mut buf, &self.pwd)?;
+                buf.push_str(",\"clients\":[");
+                for (i, client) in self.clients.iter().enumerate() {
+                    if i != 0 {
+                        buf.push(',');
+                    }
+                    buf.push_str("{\"client\":");
+                    json::write_str(&mut buf, &client.name)?;
+                    buf.push_str(",\"bufname\":");
+                    json::write_str(&mut buf, &client.bufname)?;
+                    buf.push('}');
+                }
+                buf.push_str("]}");
+                writeln!(writer, "{buf}")?;
+            }
+            Format::Tsv if self.clients.is_empty() => {
+                writeln!(writer, "{}\t{}\t\t", tsv(&self.name), tsv(&self.pwd))?;
+            }
+            Format::Tsv => {
+                for client in self.clients.iter() {
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}",
+                        tsv(&self.name),
+                        tsv(&self.pwd),
+                        tsv(&client.name),
+                        tsv(&client.bufname)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
-#[allow(unused)]
 #[derive(Debug)]
 pub struct Client {
     name: Rc<Box<str>>,
@@ -33,12 +83,24 @@ impl Client {
     }
 }
 
+// escape characters which would break a tsv record
+pub(super) fn tsv(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
 pub(crate) fn list_all(
     sessions: impl Iterator<Item = String>,
+    timeout: Option<Duration>,
     debug: bool,
 ) -> Result<Vec<Session>> {
     sessions
-        .map(|s| list_current(Context::new(s, debug)))
+        .map(|s| {
+            let mut ctx = Context::new(s, debug);
+            ctx.set_timeout(timeout);
+            list_current(ctx)
+        })
         .collect()
 }
 
@@ -70,3 +132,40 @@ pub(crate) fn list_current(mut ctx: Context) -> Result<Session> {
     ctx.query_kak(QueryContext::new_sh(vec!["pwd".into()], true), None)
         .map(|mut pwd| Session::new(ctx.session(), pwd.pop().unwrap_or_default(), clients))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_write_to() -> Result<()> {
+        let session = Session::new(
+            Rc::new("s".into()),
+            "/tmp/a\tb".into(),
+            vec![
+                Client::new(Rc::new("c0".into()), "*scratch*".into()),
+                Client::new(Rc::new("c1".into()), "a \"b\"".into()),
+            ],
+        );
+        let mut buf = Vec::new();
+        session.write_to(&mut buf, &Format::Json)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            concat!(
+                r#"{"session":"s","pwd":"/tmp/a\tb","clients":["#,
+                r#"{"client":"c0","bufname":"*scratch*"},"#,
+                r#"{"client":"c1","bufname":"a \"b\""}]}"#,
+                "\n"
+            )
+        );
+        let mut buf = Vec::new();
+        session.write_to(&mut buf, &Format::Tsv)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "s\t/tmp/a\\tb\tc0\t*scratch*\ns\t/tmp/a\\tb\tc1\ta \"b\"\n"
+        );
+        let mut buf = Vec::new();
+        Session::new(Rc::new("s".into()), "/".into(), vec![]).write_to(&mut buf, &Format::Tsv)?;
+        assert_eq!(String::from_utf8(buf)?, "s\t/\t\t\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{Context, PROJECT_TIMEOUT, QueryContext, Result};
+use crate::kamp::kak;
+
+/// Picks running session whose working directory is the nearest ancestor of dir.
+/// Sessions failing to respond are skipped.
+pub(crate) fn project_session(
+    dir: &Path,
+    timeout: Option<Duration>,
+    debug: bool,
+) -> Result<Option<String>> {
+    let sessions = String::from_utf8(kak::list_sessions()?)?;
+    let dirs = sessions.lines().filter_map(|name| {
+        let mut ctx = Context::new(name, debug);
+        ctx.set_timeout(timeout.or(Some(PROJECT_TIMEOUT)));
+        let mut pwd = ctx
+            .query_kak(QueryContext::new_sh(vec!["pwd".into()], true), None)
+            .ok()?;
+        let pwd = PathBuf::from(pwd.pop()?);
+        Some((name, pwd.canonicalize().unwrap_or(pwd)))
+    });
+    Ok(nearest(dir, dirs).map(String::from))
+}
+
+// first of the deepest dirs containing dir
+fn nearest<'a>(dir: &Path, dirs: impl Iterator<Item = (&'a str, PathBuf)>) -> Option<&'a str> {
+    dirs.filter(|(_, d)| dir.starts_with(d))
+        .map(|(name, d)| (name, d.components().count()))
+        .fold(
+            None,
+            |best: Option<(&str, usize)>, (name, depth)| match best {
+                Some((_, n)) if n >= depth => best,
+                _ => Some((name, depth)),
+            },
+        )
+        .map(|(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_nearest() {
+        let dirs = || {
+            [
+                ("home", "/home/u"),
+                ("kamp", "/home/u/src/kamp"),
+                ("kamp2", "/home/u/src/kamp"),
+                ("kampx", "/home/u/src/kampx"),
+            ]
+            .into_iter()
+            .map(|(name, d)| (name, PathBuf::from(d)))
+        };
+        assert_eq!(
+            nearest(Path::new("/home/u/src/kamp/src"), dirs()),
+            Some("kamp")
+        );
+        assert_eq!(nearest(Path::new("/home/u/src/kamp"), dirs()), Some("kamp"));
+        assert_eq!(nearest(Path::new("/home/u/src"), dirs()), Some("home"));
+        assert_eq!(nearest(Path::new("/tmp"), dirs()), None);
+    }
+}
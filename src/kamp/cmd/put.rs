@@ -0,0 +1,50 @@
+use std::io::Read;
+use std::path::Path;
+
+use super::{Context, Error, Result};
+use crate::kamp::context::command::{append_command, with_register};
+use crate::kamp::context::fifo::{TempFile, file_expansion};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    ReplaceBuffer,
+    ReplaceSelections,
+    Append,
+    InsertBefore,
+}
+
+/// Puts content of file, or stdin if file is none, into buffer.
+/// Data is passed as a file read by kakoune itself,
+/// so it isn't subject to command line quoting or size limits.
+pub(crate) fn put<R: Read>(
+    ctx: &Context,
+    mode: Mode,
+    file: Option<String>,
+    mut input: R,
+    buffer_ctx: Option<(String, i32)>,
+) -> Result<()> {
+    if ctx.is_draft() && buffer_ctx.is_none() {
+        return Err(Error::InvalidContext("either client or buffer is required"));
+    }
+    let tmp;
+    let path = match file.filter(|f| f != "-") {
+        Some(file) => Path::new(&file).canonicalize()?,
+        None => {
+            tmp = TempFile::new(&ctx.session())?;
+            let mut f = std::fs::File::create(tmp.path())?;
+            std::io::copy(&mut input, &mut f)?;
+            tmp.path().to_path_buf()
+        }
+    };
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("path is not valid utf8: {}", path.display()))?;
+    let value = file_expansion(path)?;
+    let body = match mode {
+        Mode::ReplaceBuffer => with_register(&value, "execute-keys -draft '%R'"),
+        Mode::ReplaceSelections => with_register(&value, "execute-keys R"),
+        Mode::InsertBefore => with_register(&value, "execute-keys P"),
+        Mode::Append => append_command(&value),
+    };
+    ctx.send(body, buffer_ctx).map(drop)
+}
@@ -0,0 +1,146 @@
+use super::{Context, Error, QueryContext, QueryKeyVal, Result, lex};
+use crate::kamp::kak;
+
+/// Resolves session name to the running session it is an unambiguous prefix of.
+/// Unknown name is returned as is.
+pub(crate) fn resolve_session(name: String) -> Result<String> {
+    // cheap check for exact name, which is the common case
+    if kak::session_path(&name).exists() {
+        return Ok(name);
+    }
+    let sessions = String::from_utf8(kak::list_sessions()?)?;
+    by_prefix("session", name, sessions.lines())
+}
+
+/// Resolves client name to the client of session in context it is an unambiguous prefix of.
+/// Unknown name is returned as is.
+pub(crate) fn resolve_client(ctx: &Context, name: String) -> Result<String> {
+    // exact name is the common case, which costs no list
+    let exists = format!("evaluate-commands -client {} nop", lex::quote(&name));
+    match ctx.send(exists, None) {
+        Ok(_) => return Ok(name),
+        // no such client
+        Err(Error::KakEvalCatch { .. }) => {}
+        Err(e) => return Err(e),
+    }
+    let query = QueryContext::new(
+        QueryKeyVal::Val("client_list".into()),
+        Default::default(),
+        Default::default(),
+        false,
+    );
+    let clients = ctx.query_kak(query, None)?;
+    by_prefix("client", name, clients.iter().map(String::as_str))
+}
+
+/// Resolves client selector, the part after '@':
+/// `first` is the first client of `client_list`,
+/// `last-focused` is the client which was created or focused last, tracked by `kamp init` script,
+/// or the first one if that client is closed,
+/// `for-buffer:<name>` is the first client displaying buffer with the name or path.
+pub(crate) fn select_client(ctx: &Context, selector: &str) -> Result<String> {
+    let query = |key_val| QueryContext::new(key_val, Default::default(), Default::default(), false);
+    let clients = ctx.query_kak(query(QueryKeyVal::Val("client_list".into())), None)?;
+    let client = match selector {
+        "first" => clients.into_iter().next(),
+        "last-focused" => {
+            let focused =
+                ctx.query_kak(query(QueryKeyVal::Opt("kamp_focused_client".into())), None)?;
+            last_focused(clients, &focused)
+        }
+        _ => {
+            let Some(name) = selector.strip_prefix("for-buffer:") else {
+                return Err(anyhow::anyhow!("unknown client selector: @{selector}").into());
+            };
+            // every client in a single request
+            let body = clients
+                .iter()
+                .map(|c| {
+                    format!(
+                        "evaluate-commands -client {} %{{ echo -quoting kakoune -to-file %opt<kamp_out> %val<bufname> %val<buffile> }}",
+                        lex::quote(c)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let output = if clients.is_empty() {
+                String::new()
+            } else {
+                ctx.send(body, None)?
+            };
+            let words = lex::split(&output).map_err(anyhow::Error::new)?;
+            if words.len() != clients.len() * 2 {
+                return Err(anyhow::anyhow!("unexpected bufname output: {output:?}").into());
+            }
+            clients
+                .into_iter()
+                .zip(words.chunks(2))
+                .find(|(_, names)| names.iter().any(|n| n == name))
+                .map(|(client, _)| client)
+        }
+    };
+    client.ok_or(Error::InvalidContext("no client matches the selector"))
+}
+
+// focused client is not reset on close, so it may be gone
+fn last_focused(clients: Vec<String>, focused: &[String]) -> Option<String> {
+    let first = clients.first().cloned();
+    clients.into_iter().find(|c| focused.contains(c)).or(first)
+}
+
+fn by_prefix<'a>(
+    kind: &'static str,
+    name: String,
+    candidates: impl Iterator<Item = &'a str>,
+) -> Result<String> {
+    let mut matches = Vec::new();
+    for candidate in candidates {
+        if candidate == name {
+            return Ok(name);
+        }
+        if candidate.starts_with(&name) {
+            matches.push(candidate.to_owned());
+        }
+    }
+    match matches.len() {
+        0 => Ok(name),
+        1 => Ok(matches.pop().unwrap()),
+        _ => Err(Error::Ambiguous {
+            kind,
+            name,
+            candidates: matches,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_by_prefix() -> Result<()> {
+        let names = ["proj-1234", "client1", "client10", "main"];
+        let resolve = |name: &str| by_prefix("client", name.into(), names.into_iter());
+        assert_eq!(resolve("proj")?, "proj-1234");
+        assert_eq!(resolve("client1")?, "client1");
+        assert_eq!(resolve("other")?, "other");
+        assert!(matches!(
+            resolve("cl"),
+            Err(Error::Ambiguous { candidates, .. }) if candidates == ["client1", "client10"]
+        ));
+        Ok(())
+    }
+    #[test]
+    fn test_last_focused() {
+        let clients = || vec!["client0".to_string(), "client1".to_string()];
+        assert_eq!(
+            last_focused(clients(), &["client1".into()]).as_deref(),
+            Some("client1")
+        );
+        assert_eq!(
+            last_focused(clients(), &["client2".into()]).as_deref(),
+            Some("client0")
+        );
+        assert_eq!(last_focused(clients(), &[]).as_deref(), Some("client0"));
+        assert_eq!(last_focused(vec![], &["client2".into()]), None);
+    }
+}
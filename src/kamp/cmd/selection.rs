@@ -0,0 +1,159 @@
+use std::io::Write;
+
+use super::{Context, Error, Result, lex};
+use crate::argv::get::Format;
+use crate::kamp::json;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Coord {
+    line: u32,
+    column: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Selection {
+    anchor: Coord,
+    cursor: Coord,
+    length: usize,
+    text: String,
+}
+
+impl Selection {
+    /// Writes selection according to format:
+    ///
+    /// plain: `<anchor_line>.<anchor_column>,<cursor_line>.<cursor_column>\t<text>`
+    ///
+    /// json: `{"anchor":{"line":1,"column":1},"cursor":{"line":1,"column":1},"length":1,"text":"a"}`
+    pub fn write_to<W: Write>(&self, mut writer: W, format: &Format, split_by: char) -> Result<()> {
+        let Selection {
+            anchor,
+            cursor,
+            length,
+            text,
+        } = self;
+        match format {
+            Format::Plain => write!(
+                writer,
+                "{}.{},{}.{}\t{text}{split_by}",
+                anchor.line, anchor.column, cursor.line, cursor.column
+            )?,
+            Format::Json => {
+                let mut buf = String::new();
+                for (key, coord) in [("anchor", anchor), ("cursor", cursor)] {
+                    buf.push(if buf.is_empty() { '{' } else { ',' });
+                    buf.push_str(&format!(
+                        "\"{key}\":{{\"line\":{},\"column\":{}}}",
+                        coord.line, coord.column
+                    ));
+                }
+                buf.push_str(&format!(",\"length\":{length},\"text\":"));
+                json::write_str(&mut buf, text)?;
+                buf.push('}');
+                writeln!(writer, "{buf}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn selections(
+    ctx: &Context,
+    buffer_ctx: Option<(String, i32)>,
+) -> Result<Vec<Selection>> {
+    if matches!(buffer_ctx, Some((_, n)) if n != 1) {
+        return Err(Error::InvalidContext("single buffer is required"));
+    }
+    if ctx.is_draft() && buffer_ctx.is_none() {
+        return Err(Error::InvalidContext("either client or buffer is required"));
+    }
+    // single echo so all three lists describe the very same selections
+    let output = ctx.send(
+        "echo -quoting kakoune -to-file %opt<kamp_out> %val<selections_desc> %val<selections_length> %val<selections>",
+        buffer_ctx,
+    )?;
+    parse(&output)
+}
+
+fn parse(output: &str) -> Result<Vec<Selection>> {
+    let words = lex::split(output).map_err(anyhow::Error::new)?;
+    if words.len() % 3 != 0 {
+        return Err(anyhow::anyhow!("unexpected selections output: {output:?}").into());
+    }
+    let n = words.len() / 3;
+    let (desc, rest) = words.split_at(n);
+    let (length, text) = rest.split_at(n);
+    desc.iter()
+        .zip(length)
+        .zip(text)
+        .map(|((desc, length), text)| {
+            let (anchor, cursor) = desc
+                .split_once(',')
+                .ok_or_else(|| anyhow::anyhow!("invalid selection desc: {desc:?}"))?;
+            Ok(Selection {
+                anchor: parse_coord(anchor)?,
+                cursor: parse_coord(cursor)?,
+                length: length.parse().map_err(anyhow::Error::new)?,
+                text: text.clone(),
+            })
+        })
+        .collect()
+}
+
+fn parse_coord(s: &str) -> Result<Coord> {
+    let err = || anyhow::anyhow!("invalid selection coord: {s:?}");
+    let (line, column) = s.split_once('.').ok_or_else(err)?;
+    Ok(Coord {
+        line: line.parse().map_err(|_| err())?,
+        column: column.parse().map_err(|_| err())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse() -> Result<()> {
+        let sel = parse("'1.1,1.3' '2.5,2.1' '3' '5' 'a''b' '\nabcd'")?;
+        assert_eq!(
+            sel,
+            vec![
+                Selection {
+                    anchor: Coord { line: 1, column: 1 },
+                    cursor: Coord { line: 1, column: 3 },
+                    length: 3,
+                    text: "a'b".into(),
+                },
+                Selection {
+                    anchor: Coord { line: 2, column: 5 },
+                    cursor: Coord { line: 2, column: 1 },
+                    length: 5,
+                    text: "\nabcd".into(),
+                },
+            ]
+        );
+        assert!(parse("'1.1,1.1' '1'").is_err());
+        assert!(parse("'1.1' '1' 'a'").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_write_to() -> Result<()> {
+        let sel = Selection {
+            anchor: Coord { line: 1, column: 2 },
+            cursor: Coord { line: 3, column: 4 },
+            length: 2,
+            text: "a\"".into(),
+        };
+        let mut buf = Vec::new();
+        sel.write_to(&mut buf, &Format::Plain, '\n')?;
+        sel.write_to(&mut buf, &Format::Json, '\0')?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            concat!(
+                "1.2,3.4\ta\"\n",
+                r#"{"anchor":{"line":1,"column":2},"cursor":{"line":3,"column":4},"length":2,"text":"a\""}"#,
+                "\n"
+            )
+        );
+        Ok(())
+    }
+}
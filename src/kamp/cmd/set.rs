@@ -0,0 +1,63 @@
+use super::{Context, Result, lex};
+use crate::argv::set::SubCommand;
+use crate::kamp::context::fifo::TempFile;
+
+pub(crate) fn set(
+    ctx: &Context,
+    subcommand: SubCommand,
+    stdin: Option<Vec<String>>,
+    buffer_ctx: Option<(String, i32)>,
+) -> Result<()> {
+    let body = match subcommand {
+        SubCommand::Option(o) => {
+            let mode = match (o.add, o.remove) {
+                (true, true) => {
+                    return Err(anyhow::anyhow!("--add and --remove are mutually exclusive").into());
+                }
+                (true, false) => " -add",
+                (false, true) => " -remove",
+                (false, false) => "",
+            };
+            let cmd = format!("set-option{mode} {} {}", o.scope, lex::quote(&o.name));
+            command(cmd, o.values, stdin)
+        }
+        SubCommand::Register(o) => {
+            let cmd = format!("set-register {}", lex::quote(&o.name));
+            command(cmd, o.values, stdin)
+        }
+    };
+    let tmp = TempFile::with_data(&ctx.session(), body.as_bytes())?;
+    ctx.send(
+        format!("evaluate-commands {}", tmp.expansion()?),
+        buffer_ctx,
+    )
+    .map(drop)
+}
+
+fn command(mut cmd: String, values: Vec<String>, stdin: Option<Vec<String>>) -> String {
+    for value in values.iter().chain(stdin.iter().flatten()) {
+        cmd.push(' ');
+        cmd.push_str(&lex::quote(value));
+    }
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_command() {
+        assert_eq!(
+            command("set-register a".into(), vec![], None),
+            "set-register a"
+        );
+        assert_eq!(
+            command(
+                "set-register a".into(),
+                vec!["x y".into(), "it's".into()],
+                Some(vec!["".into(), "%{}".into()])
+            ),
+            "set-register a 'x y' 'it''s' '' '%{}'"
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Context, Error, Result, START_TIMEOUT};
+use crate::kamp::kak;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Starts daemon session unless it is running and waits until it is ready for requests.
+/// Returns whether the session was started, by this call or a concurrent one.
+pub(crate) fn start(session: &str, timeout: Option<Duration>, debug: bool) -> Result<bool> {
+    if is_running(session) {
+        return Ok(false);
+    }
+    let timeout = timeout.unwrap_or(START_TIMEOUT);
+    let deadline = Instant::now() + timeout;
+    let mut child = kak::daemon(session)?;
+    while !is_running(session) {
+        // kak exits with zero code after forking the daemon
+        if let Some(status) = child.try_wait()?
+            && !status.success()
+        {
+            // a concurrent start won the race for the session name, which is as good
+            if is_running(session) {
+                break;
+            }
+            return Err(Error::KakUnexpectedExit(status.code().unwrap_or(1)));
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::Timeout(timeout));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    // socket is listening before kakrc is sourced,
+    // a round trip makes sure the session is done with it
+    let mut ctx = Context::new(session, debug);
+    ctx.set_timeout(Some(deadline.saturating_duration_since(Instant::now())));
+    ctx.send("nop", None).map(|_| true)
+}
+
+fn is_running(session: &str) -> bool {
+    UnixStream::connect(kak::session_path(session)).is_ok()
+}
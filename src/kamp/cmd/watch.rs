@@ -0,0 +1,301 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use super::list::tsv;
+use super::{Context, Error, Result, lex};
+use crate::argv::get::Format;
+use crate::kamp::context::fifo::Fifo;
+use crate::kamp::json;
+
+static STOP: AtomicBool = AtomicBool::new(false);
+// write end of the events fifo, written to on signal to wake up the reader
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+
+#[derive(Debug, PartialEq, Eq)]
+struct Event {
+    hook: String,
+    client: String,
+    bufname: String,
+    param: String,
+}
+
+impl Event {
+    /// Writes event according to format:
+    ///
+    /// plain: `<hook>\t<client>\t<bufname>\t<param>`, tabs and newlines are escaped
+    ///
+    /// json: `{"hook":"<hook>","client":"<client>","bufname":"<bufname>","param":"<param>"}`
+    fn write_to<W: Write>(&self, writer: &mut W, format: &Format) -> Result<()> {
+        match format {
+            Format::Plain => writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                tsv(&self.hook),
+                tsv(&self.client),
+                tsv(&self.bufname),
+                tsv(&self.param)
+            )?,
+            Format::Json => {
+                let mut buf = String::new();
+                for (key, value) in [
+                    ("hook", &self.hook),
+                    ("client", &self.client),
+                    ("bufname", &self.bufname),
+                    ("param", &self.param),
+                ] {
+                    buf.push(if buf.is_empty() { '{' } else { ',' });
+                    json::write_str(&mut buf, key)?;
+                    buf.push(':');
+                    json::write_str(&mut buf, value)?;
+                }
+                buf.push('}');
+                writeln!(writer, "{buf}")?;
+            }
+        }
+        writer.flush().map_err(From::from)
+    }
+}
+
+/// Receives records of hooks installed with [`Watcher::hook`] in a session.
+/// SIGHUP, SIGINT and SIGTERM stop receiving instead of terminating the process,
+/// so that hooks can be removed.
+pub(super) struct Watcher {
+    fifo: Fifo,
+    reader: File,
+    _keep: File,
+    group: String,
+    pending: Vec<u8>,
+}
+
+impl Watcher {
+    pub fn new(ctx: &Context) -> Result<Self> {
+        let fifo = Fifo::new(&ctx.session())?;
+        // open read end without blocking, then hold a write end of our own,
+        // so kakoune never blocks on open and reader never sees EOF between records
+        let reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(fifo.path())?;
+        let keep = OpenOptions::new().write(true).open(fifo.path())?;
+        set_blocking(&reader)?;
+        catch_signals(&keep)?;
+        Ok(Watcher {
+            fifo,
+            reader,
+            _keep: keep,
+            group: format!("kamp-watch-{}", std::process::id()),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Command to install global hook sending a record of 4 words, each either
+    /// a quoted word or an expansion, to the watcher.
+    /// Opening the fifo would block kakoune if the watcher was gone without removing its hooks,
+    /// e.g. killed with SIGKILL, so the hook checks the process is alive first
+    /// and removes the whole group otherwise.
+    pub fn hook(&self, hook: &str, filter: &str, words: [&str; 4]) -> Result<String> {
+        let path = self.fifo.path().to_str().ok_or_else(|| {
+            anyhow::anyhow!(
+                "fifo path is not valid utf8: {}",
+                self.fifo.path().display()
+            )
+        })?;
+        Ok(format!(
+            "hook -group {group} global {} {} %🦀 evaluate-commands %sh{{ kill -0 {} 2>/dev/null || echo 'remove-hooks global {group}; fail kamp watcher is gone' }}; echo -quoting kakoune -end-of-line -to-file {} {} 🦀",
+            lex::quote(hook),
+            lex::quote(filter),
+            std::process::id(),
+            lex::quote(path),
+            words.join(" "),
+            group = self.group,
+        ))
+    }
+
+    /// Command to remove all hooks installed by the watcher
+    pub fn remove(&self) -> String {
+        format!("remove-hooks global {}", self.group)
+    }
+
+    /// Blocks until records arrive, none if stopped by a signal.
+    pub fn next(&mut self) -> Result<Option<Vec<[String; 4]>>> {
+        let mut buf = [0; 8192];
+        loop {
+            if STOP.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            let n = match self.reader.read(&mut buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                res => res?,
+            };
+            self.pending.extend_from_slice(&buf[..n]);
+            let records = parse(&mut self.pending);
+            if !records.is_empty() {
+                return Ok(Some(records));
+            }
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        WAKE_FD.store(-1, Ordering::SeqCst);
+    }
+}
+
+/// Streams events of a temporary global hook until interrupted.
+/// The hook is removed on exit, on SIGHUP, SIGINT, SIGTERM or when writer is closed.
+pub(crate) fn watch<W: Write>(
+    ctx: &Context,
+    hook: &str,
+    filter: &str,
+    format: &Format,
+    mut writer: W,
+) -> Result<()> {
+    let mut watcher = Watcher::new(ctx)?;
+    ctx.send(
+        watcher.hook(
+            hook,
+            filter,
+            [
+                &lex::quote(hook),
+                "%val<client>",
+                "%val<bufname>",
+                "%val<hook_param>",
+            ],
+        )?,
+        None,
+    )?;
+
+    let res = stream(&mut watcher, &mut writer, format);
+    let removed = ctx.send(watcher.remove(), None);
+    match res {
+        // consumer is gone, e.g. 'kamp watch ... | head -1'
+        Err(Error::IO(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+        res => res?,
+    }
+    removed.map(drop)
+}
+
+fn stream<W: Write>(watcher: &mut Watcher, writer: &mut W, format: &Format) -> Result<()> {
+    while let Some(records) = watcher.next()? {
+        for [hook, client, bufname, param] in records {
+            let event = Event {
+                hook,
+                client,
+                bufname,
+                param,
+            };
+            event.write_to(writer, format)?;
+        }
+    }
+    Ok(())
+}
+
+// takes complete records out of pending data, records are newline terminated
+// and newline outside of quotes can only be the terminator
+fn parse(pending: &mut Vec<u8>) -> Vec<[String; 4]> {
+    if !pending.ends_with(b"\n") {
+        return Vec::new();
+    }
+    let Ok(s) = std::str::from_utf8(pending) else {
+        return Vec::new();
+    };
+    let Ok(words) = lex::split(s) else {
+        return Vec::new();
+    };
+    if words.len() % 4 != 0 {
+        return Vec::new();
+    }
+    pending.clear();
+    words
+        .chunks_exact(4)
+        .map(|w| [w[0].clone(), w[1].clone(), w[2].clone(), w[3].clone()])
+        .collect()
+}
+
+fn set_blocking(f: &File) -> std::io::Result<()> {
+    let fd = f.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn catch_signals(wake: &File) -> std::io::Result<()> {
+    WAKE_FD.store(wake.as_raw_fd(), Ordering::SeqCst);
+    for sig in [libc::SIGHUP, libc::SIGINT, libc::SIGTERM] {
+        let handler = on_signal as extern "C" fn(libc::c_int);
+        if unsafe { libc::signal(sig, handler as libc::sighandler_t) } == libc::SIG_ERR {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+extern "C" fn on_signal(_: libc::c_int) {
+    // only async-signal-safe calls here
+    STOP.store(true, Ordering::SeqCst);
+    let fd = WAKE_FD.load(Ordering::SeqCst);
+    if fd != -1 {
+        unsafe { libc::write(fd, b"\n".as_ptr().cast(), 1) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse() {
+        let event = |hook: &str, param: &str| -> [String; 4] {
+            [hook.into(), "client0".into(), "a b".into(), param.into()]
+        };
+        let mut pending = b"'BufWritePost' 'client0' 'a b' '/tmp/a b'".to_vec();
+        assert!(parse(&mut pending).is_empty());
+        pending.extend_from_slice(b"\n'InsertChar' 'client0' 'a b' '\n");
+        assert!(parse(&mut pending).is_empty());
+        pending.extend_from_slice(b"'\n");
+        assert_eq!(
+            parse(&mut pending),
+            vec![event("BufWritePost", "/tmp/a b"), event("InsertChar", "\n")]
+        );
+        assert!(pending.is_empty());
+    }
+    #[test]
+    fn test_hook() -> Result<()> {
+        let watcher = Watcher::new(&Context::new("kamp-test", false))?;
+        let path = watcher.fifo.path().to_str().unwrap();
+        let pid = std::process::id();
+        assert_eq!(
+            watcher.hook(
+                "BufWritePost",
+                ".*'s",
+                [&lex::quote("BufWritePost"), "%val<client>", "''", "''"]
+            )?,
+            format!(
+                "hook -group kamp-watch-{pid} global 'BufWritePost' '.*''s' %🦀 evaluate-commands %sh{{ kill -0 {pid} 2>/dev/null || echo 'remove-hooks global kamp-watch-{pid}; fail kamp watcher is gone' }}; echo -quoting kakoune -end-of-line -to-file '{path}' 'BufWritePost' %val<client> '' '' 🦀"
+            )
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_write_to() -> Result<()> {
+        let event = Event {
+            hook: "InsertChar".into(),
+            client: "c".into(),
+            bufname: "b".into(),
+            param: "\t".into(),
+        };
+        let mut buf = Vec::new();
+        event.write_to(&mut buf, &Format::Plain)?;
+        event.write_to(&mut buf, &Format::Json)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "InsertChar\tc\tb\t\\t\n{\"hook\":\"InsertChar\",\"client\":\"c\",\"bufname\":\"b\",\"param\":\"\\t\"}\n"
+        );
+        Ok(())
+    }
+}
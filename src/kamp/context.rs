@@ -1,40 +1,36 @@
-mod lex;
+pub(crate) mod command;
+pub(crate) mod fifo;
+pub(crate) mod lex;
 
 use super::cmd::{QueryContext, QueryType, Quoting};
 use super::kak;
 use super::{Error, Result};
+use fifo::Fifos;
 use std::io::{Cursor, prelude::*};
 use std::path::Path;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{SyncSender, sync_channel};
 use std::thread;
+use std::time::{Duration, Instant};
 
 const END_TOKEN: &str = "<<EEND>>";
 
 #[derive(Debug, Clone)]
 pub(crate) struct Context {
-    fifo_out: Arc<Box<Path>>,
-    fifo_err: Arc<Box<Path>>,
     session: Rc<Box<str>>,
     client: Option<Rc<Box<str>>>,
+    timeout: Option<Duration>,
     debug: bool,
 }
 
 impl Context {
     pub fn new<S: AsRef<str>>(session: S, debug: bool) -> Self {
-        let session = session.as_ref();
-        let mut out = std::env::temp_dir();
-        out.push(format!("kamp-{session}"));
-        let mut err = out.clone();
-        out.set_extension("out");
-        err.set_extension("err");
-
         Context {
-            fifo_out: Arc::new(out.into_boxed_path()),
-            fifo_err: Arc::new(err.into_boxed_path()),
-            session: Rc::new(session.into()),
+            session: Rc::new(session.as_ref().into()),
             client: None,
+            timeout: None,
             debug,
         }
     }
@@ -48,6 +44,10 @@ impl Context {
         }
     }
 
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
     pub fn client(&self) -> Option<Rc<Box<str>>> {
         self.client.clone()
     }
@@ -75,14 +75,16 @@ impl Context {
             cmd.push_str(&status.to_string());
         }
 
-        kak::pipe(self.session.as_ref(), cmd)
-            .map_err(From::from)
-            .and_then(|status| self.check_status(status))
+        // kakoune is gone before anything could be acknowledged
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        self.pipe(cmd, None, deadline)
     }
 
     pub fn send(&self, body: impl AsRef<str>, buffer_ctx: Option<(String, i32)>) -> Result<String> {
         let body = body.as_ref();
+        let fifos = Fifos::new(&self.session)?;
         let mut buf = Cursor::new(Vec::with_capacity(512));
+        write_fifo_opts(&mut buf, &fifos)?;
         writeln!(buf, "try %🐪")?;
         match (buffer_ctx, self.client()) {
             (Some((b, n)), _) => {
@@ -115,11 +117,12 @@ impl Context {
             eprintln!("{cmd}");
             eprintln!("cmd.len: {}", cmd.len());
         }
+        let cancel = Arc::new(AtomicBool::new(false));
         let (tx, rx) = sync_channel(1);
-        let err_h = self.read_fifo_err(tx.clone());
-        let out_h = self.read_fifo_out(tx);
+        let err_h = read_fifo_err(fifos.err.clone(), tx.clone(), cancel.clone());
+        let out_h = read_fifo_out(fifos.out.clone(), tx, cancel.clone());
 
-        let err_path = self.fifo_err.clone();
+        let err_path = fifos.err.clone();
         let handle = thread::spawn(move || {
             match rx.recv().map_err(anyhow::Error::new)? {
                 Err(kak_err) => err_h
@@ -147,17 +150,18 @@ impl Context {
             }
         });
 
-        let status = kak::pipe(self.session.as_ref(), cmd.as_bytes())?;
-        match (self.check_status(status), handle.join().unwrap()) {
-            (Ok(_), Ok(s)) => Ok(s),
-            (Ok(_), Err(e)) => Err(e),
-            (Err(e), _) => Err(e),
-        }
+        // on failure nothing is going to open the fifos, so don't wait for readers
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        self.pipe(&cmd, Some(&fifos.ack), deadline)?;
+        self.wait(handle, &fifos, &cancel, deadline)
+            .map_err(|e| with_command(e, cmd))
     }
 
     pub fn connect(&self, body: impl AsRef<str>) -> Result<()> {
         let body = body.as_ref();
+        let fifos = Fifos::new(&self.session)?;
         let mut buf = Cursor::new(Vec::with_capacity(512));
+        write_fifo_opts(&mut buf, &fifos)?;
 
         if body.is_empty() {
             write!(buf, "echo -to-file %opt<kamp_out> {END_TOKEN}")?;
@@ -178,11 +182,12 @@ impl Context {
             eprintln!("{cmd}");
             eprintln!("cmd.len: {}", cmd.len());
         }
+        let cancel = Arc::new(AtomicBool::new(false));
         let (tx, rx) = sync_channel(1);
-        let err_h = self.read_fifo_err(tx.clone());
-        let out_h = self.read_fifo_out(tx);
+        let err_h = read_fifo_err(fifos.err.clone(), tx.clone(), cancel.clone());
+        let out_h = read_fifo_out(fifos.out.clone(), tx, cancel.clone());
 
-        let err_path = self.fifo_err.clone();
+        let err_path = fifos.err.clone();
         let handle = thread::spawn(move || {
             match rx.recv().map_err(anyhow::Error::new)? {
                 Err(kak_err) => err_h
@@ -205,12 +210,12 @@ impl Context {
             }
         });
 
-        let status = kak::connect(self.session.as_ref(), &cmd.into_boxed_str())?;
-        match (self.check_status(status), handle.join().unwrap()) {
-            (Ok(_), Ok(_)) => Ok(()),
-            (Ok(_), Err(e)) => Err(e),
-            (Err(e), _) => Err(e),
-        }
+        let status = kak::connect(self.session.as_ref(), &cmd.clone().into_boxed_str())?;
+        self.check_status(status)?;
+        // client is gone, what is left must not take longer than any other request
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        self.wait(handle, &fifos, &cancel, deadline)
+            .map_err(|e| with_command(e, cmd))
     }
 
     pub fn query_kak(
@@ -257,6 +262,65 @@ impl Context {
             })
     }
 
+    // talk to the session's socket directly and fall back to 'kak -p'
+    // if connecting or writing fails, e.g. socket isn't where we expect it to be,
+    // or if the server closed the connection without evaluating the command,
+    // e.g. it speaks another protocol version; the command creates ack file first thing
+    fn pipe(
+        &self,
+        cmd: impl AsRef<[u8]>,
+        ack: Option<&Path>,
+        deadline: Option<Instant>,
+    ) -> Result<()> {
+        let cmd = cmd.as_ref();
+        let remaining = || deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        let timed_out = || Error::Timeout(self.timeout.unwrap_or_default());
+        let e = match kak::send(self.session.as_ref(), cmd, remaining()) {
+            Ok(()) if ack.is_none_or(Path::exists) => return Ok(()),
+            Ok(()) => anyhow::anyhow!("command was not evaluated"),
+            // server is busy, the command may still be evaluated
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => return Err(timed_out()),
+            Err(e) => e.into(),
+        };
+        if self.debug {
+            eprintln!("kamp: native send failed: {e}, falling back to kak -p");
+        }
+        match kak::pipe(self.session.as_ref(), cmd, remaining()) {
+            Ok(status) => self.check_status(status),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => Err(timed_out()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn wait<T>(
+        &self,
+        handle: thread::JoinHandle<Result<T>>,
+        fifos: &Fifos,
+        cancel: &AtomicBool,
+        deadline: Option<Instant>,
+    ) -> Result<T> {
+        let (Some(timeout), Some(deadline)) = (self.timeout, deadline) else {
+            return handle.join().unwrap();
+        };
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                cancel.store(true, Ordering::Relaxed);
+                // readers may not have reached open yet, so retry for a while
+                for _ in 0..100 {
+                    fifos.unblock();
+                    if handle.is_finished() {
+                        let _ = handle.join();
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+                return Err(Error::Timeout(timeout));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        handle.join().unwrap()
+    }
+
     fn check_status(&self, status: std::process::ExitStatus) -> Result<()> {
         if status.success() {
             return Ok(());
@@ -266,40 +330,80 @@ impl Context {
             None => anyhow::Error::msg("kak terminated by signal").into(),
         })
     }
+}
 
-    fn read_fifo_err(
-        &self,
-        send_ch: SyncSender<Result<String>>,
-    ) -> thread::JoinHandle<anyhow::Result<()>> {
-        let path = self.fifo_err.clone();
-        thread::spawn(move || {
-            let mut buf = String::new();
-            std::fs::OpenOptions::new()
-                .read(true)
-                .open(path.as_ref())
-                .and_then(|mut f| f.read_to_string(&mut buf))?;
-            send_ch
-                .send(Err(Error::KakEvalCatch(buf)))
-                .map_err(anyhow::Error::new)
-        })
+fn with_command(e: Error, cmd: String) -> Error {
+    match e {
+        Error::KakEvalCatch { error, .. } => Error::KakEvalCatch {
+            error,
+            command: Some(cmd),
+        },
+        e => e,
     }
+}
 
-    fn read_fifo_out(
-        &self,
-        send_ch: SyncSender<Result<String>>,
-    ) -> thread::JoinHandle<anyhow::Result<()>> {
-        let path = self.fifo_out.clone();
-        thread::spawn(move || {
-            let mut buf = String::new();
-            let mut f = std::fs::OpenOptions::new().read(true).open(path.as_ref())?;
-            // END_TOKEN comes appended to the payload
-            let res = loop {
-                f.read_to_string(&mut buf)?;
-                if buf.ends_with(END_TOKEN) {
-                    break buf.trim_end_matches(END_TOKEN);
-                }
-            };
-            send_ch.send(Ok(res.into())).map_err(anyhow::Error::new)
-        })
+// point kamp_out and kamp_err at the fifos of this very request,
+// a command is evaluated as a whole so concurrent requests don't interfere
+fn write_fifo_opts<W: Write>(buf: &mut W, fifos: &Fifos) -> Result<()> {
+    for (name, path) in [("kamp_out", &fifos.out), ("kamp_err", &fifos.err)] {
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("fifo path is not valid utf8: {}", path.display()))?;
+        writeln!(
+            buf,
+            "declare-option -hidden str {name} {}",
+            lex::quote(path)
+        )?;
     }
+    let ack = fifos
+        .ack
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("fifo path is not valid utf8: {}", fifos.ack.display()))?;
+    writeln!(buf, "echo -to-file {}", lex::quote(ack))?;
+    Ok(())
+}
+
+fn read_fifo_err(
+    path: Arc<Path>,
+    send_ch: SyncSender<Result<String>>,
+    cancel: Arc<AtomicBool>,
+) -> thread::JoinHandle<anyhow::Result<()>> {
+    thread::spawn(move || {
+        let mut buf = String::new();
+        std::fs::OpenOptions::new()
+            .read(true)
+            .open(path.as_ref())
+            .and_then(|mut f| f.read_to_string(&mut buf))?;
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        send_ch
+            .send(Err(Error::KakEvalCatch {
+                error: buf,
+                command: None,
+            }))
+            .map_err(anyhow::Error::new)
+    })
+}
+
+fn read_fifo_out(
+    path: Arc<Path>,
+    send_ch: SyncSender<Result<String>>,
+    cancel: Arc<AtomicBool>,
+) -> thread::JoinHandle<anyhow::Result<()>> {
+    thread::spawn(move || {
+        let mut buf = String::new();
+        let mut f = std::fs::OpenOptions::new().read(true).open(path.as_ref())?;
+        // END_TOKEN comes appended to the payload
+        let res = loop {
+            f.read_to_string(&mut buf)?;
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            if buf.ends_with(END_TOKEN) {
+                break buf.trim_end_matches(END_TOKEN);
+            }
+        };
+        send_ch.send(Ok(res.into())).map_err(anyhow::Error::new)
+    })
 }
@@ -0,0 +1,27 @@
+/// Command to append value to the end of buffer in context,
+/// an empty buffer gets its content replaced instead.
+/// Value is expected to be a single quoted word or an expansion.
+pub(crate) fn append_command(value: &str) -> String {
+    with_register(
+        value,
+        r"try %{ execute-keys -draft '%<a-k>\A\n\z<ret>R' } catch %{ execute-keys -draft gep }",
+    )
+}
+
+/// Command to run action with value in dquote register, the register is restored afterwards.
+pub(crate) fn with_register(value: &str, action: &str) -> String {
+    format!("evaluate-commands -save-regs '\"' %🦀\nset-register dquote {value}\n{action}\n🦀")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kamp::context::lex;
+    #[test]
+    fn test_append_command() {
+        assert_eq!(
+            append_command(&lex::quote("it's\n")),
+            "evaluate-commands -save-regs '\"' %🦀\nset-register dquote 'it''s\n'\ntry %{ execute-keys -draft '%<a-k>\\A\\n\\z<ret>R' } catch %{ execute-keys -draft gep }\n🦀"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::ffi::CString;
+use std::io::{Error, Result};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+static SEQ: AtomicUsize = AtomicUsize::new(0);
+
+// fifo and its dir to remove from within a signal handler
+static ON_SIGNAL: OnceLock<(CString, CString)> = OnceLock::new();
+
+/// Pair of fifos unique to a single request, removed on drop, including when the request
+/// times out. They are left behind if kamp is killed by a signal, see README.
+#[derive(Debug)]
+pub(super) struct Fifos {
+    dir: PathBuf,
+    pub out: Arc<Path>,
+    pub err: Arc<Path>,
+    /// regular file created by the request as soon as kakoune evaluates it
+    pub ack: PathBuf,
+}
+
+impl Fifos {
+    pub fn new(session: &str) -> Result<Self> {
+        let dir = temp_dir(session)?;
+        let fifos = Fifos {
+            out: Arc::from(dir.join("out")),
+            err: Arc::from(dir.join("err")),
+            ack: dir.join("ack"),
+            dir,
+        };
+        mkfifo(&fifos.out)?;
+        mkfifo(&fifos.err)?;
+        Ok(fifos)
+    }
+
+    /// Opens both fifos for writing without blocking,
+    /// so readers waiting on open can proceed.
+    pub fn unblock(&self) {
+        for path in [&self.out, &self.err] {
+            let _ = std::fs::OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path);
+        }
+    }
+}
+
+impl Drop for Fifos {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.out);
+        let _ = std::fs::remove_file(&self.err);
+        let _ = std::fs::remove_file(&self.ack);
+        let _ = std::fs::remove_dir(&self.dir);
+    }
+}
+
+/// Single fifo, removed on drop.
+#[derive(Debug)]
+pub(crate) struct Fifo {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Fifo {
+    pub fn new(session: &str) -> Result<Self> {
+        let dir = temp_dir(session)?;
+        let fifo = Fifo {
+            path: dir.join("fifo"),
+            dir,
+        };
+        mkfifo(&fifo.path)?;
+        Ok(fifo)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the fifo on SIGHUP, SIGINT and SIGTERM as well,
+    /// only one fifo per process may be registered.
+    pub fn remove_on_signal(&self) -> Result<()> {
+        let to_c = |p: &Path| CString::new(p.as_os_str().as_bytes()).map_err(Error::other);
+        ON_SIGNAL
+            .set((to_c(&self.path)?, to_c(&self.dir)?))
+            .map_err(|_| Error::other("signal cleanup is already registered"))?;
+        for sig in [libc::SIGHUP, libc::SIGINT, libc::SIGTERM] {
+            let handler = on_signal as extern "C" fn(libc::c_int);
+            if unsafe { libc::signal(sig, handler as libc::sighandler_t) } == libc::SIG_ERR {
+                return Err(Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Fifo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_dir(&self.dir);
+    }
+}
+
+/// Regular file to pass data to kakoune, removed on drop.
+#[derive(Debug)]
+pub(crate) struct TempFile {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub fn new(session: &str) -> Result<Self> {
+        let dir = temp_dir(session)?;
+        Ok(TempFile {
+            path: dir.join("data"),
+            dir,
+        })
+    }
+
+    /// Creates temp file holding data. Data which kakoune reads through `expansion`
+    /// isn't subject to command quoting, so no text can break out of the request.
+    pub fn with_data(session: &str, data: &[u8]) -> Result<Self> {
+        let tmp = TempFile::new(session)?;
+        std::fs::write(&tmp.path, data)?;
+        Ok(tmp)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// %file expansion reading the file
+    pub fn expansion(&self) -> Result<String> {
+        let path = self.path.to_str().ok_or_else(|| {
+            Error::other(format!(
+                "temp file path is not valid utf8: {}",
+                self.path.display()
+            ))
+        })?;
+        file_expansion(path)
+    }
+}
+
+/// %file<delimiter>path<delimiter> with a delimiter which isn't part of the path
+pub(crate) fn file_expansion(path: &str) -> Result<String> {
+    ['|', '~', '!', '#', '^', '+', '=']
+        .into_iter()
+        .find(|&c| !path.contains(c))
+        .map(|c| format!("%file{c}{path}{c}"))
+        .ok_or_else(|| Error::other(format!("cannot find delimiter for path: {path:?}")))
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_dir(&self.dir);
+    }
+}
+
+extern "C" fn on_signal(sig: libc::c_int) {
+    // only async-signal-safe calls here
+    if let Some((path, dir)) = ON_SIGNAL.get() {
+        unsafe {
+            libc::unlink(path.as_ptr());
+            libc::rmdir(dir.as_ptr());
+        }
+    }
+    unsafe { libc::_exit(128 + sig) }
+}
+
+fn temp_dir(session: &str) -> Result<PathBuf> {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!(
+        "kamp-{session}-{}-{}",
+        std::process::id(),
+        SEQ.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    Ok(dir)
+}
+
+fn mkfifo(path: &Path) -> Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(Error::other)?;
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::FileTypeExt;
+    #[test]
+    fn test_fifos() -> Result<()> {
+        let a = Fifos::new("test")?;
+        let b = Fifos::new("test")?;
+        assert_ne!(a.out, b.out);
+        assert!(std::fs::metadata(&a.out)?.file_type().is_fifo());
+        assert!(std::fs::metadata(&a.err)?.file_type().is_fifo());
+        let dir = a.dir.clone();
+        drop(a);
+        assert!(!dir.exists());
+        Ok(())
+    }
+    #[test]
+    fn test_file_expansion() -> Result<()> {
+        assert_eq!(file_expansion("/tmp/a b")?, "%file|/tmp/a b|");
+        assert_eq!(file_expansion("/tmp/a|b")?, "%file~/tmp/a|b~");
+        assert!(file_expansion("|~!#^+=").is_err());
+        Ok(())
+    }
+}
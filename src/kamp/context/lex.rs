@@ -1,7 +1,7 @@
 use std::{fmt::Display, mem};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(super) struct ParseError;
+pub(crate) struct ParseError;
 
 impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -19,7 +19,7 @@ enum State {
 }
 
 /// Splits output produced with 'echo -quoting kakoune'
-pub(super) fn split(s: &str) -> Result<Vec<String>, ParseError> {
+pub(crate) fn split(s: &str) -> Result<Vec<String>, ParseError> {
     use State::*;
 
     let mut words = Vec::new();
@@ -63,10 +63,52 @@ pub(super) fn split(s: &str) -> Result<Vec<String>, ParseError> {
     Ok(words)
 }
 
+/// Splits output of several 'echo -quoting kakoune -end-of-line' into words of each line,
+/// new lines inside of quotes are part of the word
+pub(crate) fn split_lines(s: &str) -> Result<Vec<Vec<String>>, ParseError> {
+    let mut lines = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            // escaped quote toggles twice
+            '\'' => quoted = !quoted,
+            '\n' if !quoted => {
+                lines.push(split(&s[start..i])?);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if quoted {
+        return Err(ParseError);
+    }
+    if start != s.len() {
+        lines.push(split(&s[start..])?);
+    }
+    Ok(lines)
+}
+
+/// Quotes string the way 'echo -quoting kakoune' does
+pub(crate) fn quote(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len() + 2);
+    buf.push('\'');
+    buf.push_str(&s.replace('\'', "''"));
+    buf.push('\'');
+    buf
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn quote_split() {
+        for s in ["", "a", "a b", "'", "a'b", "''"] {
+            assert_eq!(split(&quote(s)).unwrap(), vec![s]);
+        }
+    }
+
     fn split_ok((input, expected): (&str, &[&str])) {
         let actual = split(input).unwrap();
         assert!(
@@ -119,4 +161,14 @@ mod tests {
             &["echo ok's", "edit 'sp buf.txt'"],
         ));
     }
+
+    #[test]
+    fn split_lines_multi() {
+        assert_eq!(
+            split_lines("'a' 'b\nc'\n\n'''\n'\n").unwrap(),
+            vec![vec!["a", "b\nc"], vec![], vec!["'\n"]]
+        );
+        assert_eq!(split_lines("'a'").unwrap(), vec![vec!["a"]]);
+        assert!(split_lines("'a\n").is_err());
+    }
 }
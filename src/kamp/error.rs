@@ -1,3 +1,5 @@
+use super::json;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("invalid context: {0}")]
@@ -6,11 +8,30 @@ pub enum Error {
     #[error("command is required")]
     CommandRequired,
 
+    #[error("ambiguous {kind} {name:?}, candidates: {}", candidates.join(", "))]
+    Ambiguous {
+        kind: &'static str,
+        name: String,
+        candidates: Vec<String>,
+    },
+
     #[error("kak exited with code: {0}")]
     KakUnexpectedExit(i32),
 
-    #[error("kak eval error: {0}")]
-    KakEvalCatch(String),
+    #[error("kak eval error: {error}")]
+    KakEvalCatch {
+        error: String,
+        command: Option<String>,
+    },
+
+    #[error("{0} check(s) failed")]
+    CheckFailed(usize),
+
+    #[error("buffer closed without saving: {0}")]
+    NotSaved(String),
+
+    #[error("timed out after {0:?}")]
+    Timeout(std::time::Duration),
 
     #[error("unexpected coordinates position: {0}")]
     UnexpectedCoordPosition(String),
@@ -35,3 +56,123 @@ pub enum Error {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Process exit code, documented in README and not supposed to change.
+    /// kamp's own codes are high enough to stay clear of kak's which are passed on as is.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::InvalidContext(_) | Error::Ambiguous { .. } => 240,
+            Error::CommandRequired => 241,
+            Error::KakEvalCatch { .. } => 242,
+            Error::UnexpectedCoordPosition(_) | Error::InvalidCoordinates { .. } => 243,
+            Error::CheckFailed(_) => 244,
+            Error::NotSaved(_) => 245,
+            Error::Timeout(_) => 246,
+            Error::KakUnexpectedExit(code) => {
+                u8::try_from(*code).ok().filter(|&c| c != 0).unwrap_or(1)
+            }
+            Error::IO(_) | Error::Fmt(_) | Error::Utf8(_) | Error::Other(_) => 1,
+        }
+    }
+
+    /// Stable name of the error kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::InvalidContext(_) => "invalid_context",
+            Error::Ambiguous { .. } => "ambiguous",
+            Error::CommandRequired => "command_required",
+            Error::KakUnexpectedExit(_) => "kak_unexpected_exit",
+            Error::KakEvalCatch { .. } => "kak_eval",
+            Error::UnexpectedCoordPosition(_) => "unexpected_coord_position",
+            Error::InvalidCoordinates { .. } => "invalid_coordinates",
+            Error::CheckFailed(_) => "check_failed",
+            Error::NotSaved(_) => "not_saved",
+            Error::Timeout(_) => "timeout",
+            Error::IO(_) => "io",
+            Error::Fmt(_) => "fmt",
+            Error::Utf8(_) => "utf8",
+            Error::Other(_) => "other",
+        }
+    }
+
+    /// Writes error as a single line JSON object:
+    /// `{"kind":"<kind>","message":"<message>","session":"<session>","client":"<client>"}`
+    /// where session and client are null if not in context,
+    /// eval errors have additional "command" field
+    pub fn write_json<W: std::fmt::Write>(
+        &self,
+        w: &mut W,
+        session: Option<&str>,
+        client: Option<&str>,
+    ) -> std::fmt::Result {
+        let opt_str = |w: &mut W, s: Option<&str>| match s {
+            Some(s) => json::write_str(w, s),
+            None => w.write_str("null"),
+        };
+        w.write_str("{\"kind\":")?;
+        json::write_str(w, self.kind())?;
+        w.write_str(",\"message\":")?;
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(e) = source {
+            message.push_str(": ");
+            message.push_str(&e.to_string());
+            source = e.source();
+        }
+        json::write_str(w, &message)?;
+        w.write_str(",\"session\":")?;
+        opt_str(w, session)?;
+        w.write_str(",\"client\":")?;
+        opt_str(w, client)?;
+        if let Error::KakEvalCatch { command, .. } = self {
+            w.write_str(",\"command\":")?;
+            opt_str(w, command.as_deref())?;
+        }
+        w.write_char('}')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(Error::InvalidContext("").exit_code(), 240);
+        assert_eq!(
+            Error::KakEvalCatch {
+                error: String::new(),
+                command: None
+            }
+            .exit_code(),
+            242
+        );
+        assert_eq!(Error::KakUnexpectedExit(3).exit_code(), 3);
+        assert_eq!(Error::KakUnexpectedExit(124).exit_code(), 124);
+        assert_eq!(Error::KakUnexpectedExit(0).exit_code(), 1);
+        assert_eq!(Error::KakUnexpectedExit(-1).exit_code(), 1);
+        assert_eq!(Error::KakUnexpectedExit(256).exit_code(), 1);
+        assert_eq!(Error::Other(anyhow::anyhow!("")).exit_code(), 1);
+    }
+
+    #[test]
+    fn test_write_json() -> std::fmt::Result {
+        let mut buf = String::new();
+        Error::InvalidContext("session is required").write_json(&mut buf, None, None)?;
+        assert_eq!(
+            buf,
+            r#"{"kind":"invalid_context","message":"invalid context: session is required","session":null,"client":null}"#
+        );
+        let mut buf = String::new();
+        let e = Error::KakEvalCatch {
+            error: "no such option".into(),
+            command: Some("echo %opt<x>".into()),
+        };
+        e.write_json(&mut buf, Some("s"), Some("c"))?;
+        assert_eq!(
+            buf,
+            r#"{"kind":"kak_eval","message":"kak eval error: no such option","session":"s","client":"c","command":"echo %opt<x>"}"#
+        );
+        Ok(())
+    }
+}
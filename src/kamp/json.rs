@@ -0,0 +1,40 @@
+use std::fmt::{Result, Write};
+
+/// Writes `s` as a quoted JSON string
+pub(crate) fn write_str<W: Write>(w: &mut W, s: &str) -> Result {
+    w.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            c if c.is_control() => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(s: &str) -> String {
+        let mut buf = String::new();
+        write_str(&mut buf, s).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(""), r#""""#);
+        assert_eq!(quote("a b"), r#""a b""#);
+        assert_eq!(quote(r#"a"b"#), r#""a\"b""#);
+        assert_eq!(quote(r"a\b"), r#""a\\b""#);
+        assert_eq!(quote("a\nb\tc"), r#""a\nb\tc""#);
+        assert_eq!(quote("\u{1}"), r#""\u0001""#);
+        assert_eq!(quote("🐪"), "\"🐪\"");
+    }
+}
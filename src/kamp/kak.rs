@@ -1,5 +1,12 @@
-use std::io::{Error, Result, Write};
-use std::process::{Command, ExitStatus, Stdio};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// MessageType::Command of Kakoune's remote protocol (src/remote.cc)
+const MSG_COMMAND: u8 = 2;
 
 pub(crate) fn list_sessions() -> Result<Vec<u8>> {
     let output = Command::new("kak").arg("-l").output()?;
@@ -14,7 +21,9 @@ pub(crate) fn list_sessions() -> Result<Vec<u8>> {
     Ok(output.stdout)
 }
 
-pub(crate) fn pipe<S, T>(session: S, cmd: T) -> Result<ExitStatus>
+/// Runs 'kak -p', the process is killed and TimedOut error is returned
+/// if it doesn't exit within timeout.
+pub(crate) fn pipe<S, T>(session: S, cmd: T, timeout: Option<Duration>) -> Result<ExitStatus>
 where
     S: AsRef<str>,
     T: AsRef<[u8]>,
@@ -30,7 +39,79 @@ where
     };
 
     stdin.write_all(cmd.as_ref())?;
-    child.wait()
+    drop(child.stdin.take());
+    let Some(timeout) = timeout else {
+        return child.wait();
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::from(ErrorKind::TimedOut));
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Sends command to the session's socket directly, the same way 'kak -p' does,
+/// without spawning a process. The protocol has no reply to a command, but the server
+/// closes the connection once the command is evaluated or rejected, which is waited for
+/// until timeout, so that the caller can tell the two apart by the command's side effects.
+pub(crate) fn send<S, T>(session: S, cmd: T, timeout: Option<Duration>) -> Result<()>
+where
+    S: AsRef<str>,
+    T: AsRef<[u8]>,
+{
+    let mut stream = UnixStream::connect(session_path(session.as_ref()))?;
+    write_command(&mut stream, cmd.as_ref())?;
+    stream.flush()?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    stream.set_read_timeout(timeout)?;
+    match stream.read_to_end(&mut Vec::new()) {
+        Ok(_) => Ok(()),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            Err(Error::from(ErrorKind::TimedOut))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// header: type (u8), message size including header (u32)
+// payload: string length (i32), string bytes
+// integers are in native byte order
+fn write_command<W: Write>(w: &mut W, cmd: &[u8]) -> Result<()> {
+    let len = i32::try_from(cmd.len()).map_err(Error::other)?;
+    let size = u32::try_from(1 + 4 + 4 + cmd.len()).map_err(Error::other)?;
+    let mut buf = Vec::with_capacity(size as usize);
+    buf.push(MSG_COMMAND);
+    buf.extend_from_slice(&size.to_ne_bytes());
+    buf.extend_from_slice(&len.to_ne_bytes());
+    buf.extend_from_slice(cmd);
+    w.write_all(&buf)
+}
+
+// mirrors session_directory() of Kakoune
+pub(crate) fn session_path(session: &str) -> PathBuf {
+    let mut path = match std::env::var_os("XDG_RUNTIME_DIR").filter(|s| !s.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("kakoune"),
+        None => std::env::temp_dir().join(format!("kakoune-{}", user_name())),
+    };
+    path.push(session);
+    path
+}
+
+// mirrors get_user_name() of Kakoune
+fn user_name() -> String {
+    let pw = unsafe { libc::getpwuid(libc::geteuid()) };
+    if !pw.is_null() {
+        let name = unsafe { std::ffi::CStr::from_ptr((*pw).pw_name) };
+        return name.to_string_lossy().into_owned();
+    }
+    std::env::var("USER").unwrap_or_default()
 }
 
 pub(crate) fn connect<S: AsRef<str>>(session: S, cmd: S) -> Result<ExitStatus> {
@@ -42,7 +123,38 @@ pub(crate) fn connect<S: AsRef<str>>(session: S, cmd: S) -> Result<ExitStatus> {
         .status()
 }
 
+/// Spawns 'kak -d -s <session>' detached from the terminal and kamp's output,
+/// so that capturing kamp's output doesn't wait for the daemon.
+pub(crate) fn daemon<S: AsRef<str>>(session: S) -> Result<Child> {
+    use std::os::unix::process::CommandExt;
+    Command::new("kak")
+        .arg("-d")
+        .arg("-s")
+        .arg(session.as_ref())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+}
+
 pub(crate) fn proxy(args: Vec<String>) -> Result<()> {
     use std::os::unix::process::CommandExt;
     Err(Command::new("kak").args(args).exec())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_write_command() -> Result<()> {
+        let mut buf = Vec::new();
+        write_command(&mut buf, b"nop")?;
+        let mut expected = vec![MSG_COMMAND];
+        expected.extend_from_slice(&12u32.to_ne_bytes());
+        expected.extend_from_slice(&3i32.to_ne_bytes());
+        expected.extend_from_slice(b"nop");
+        assert_eq!(buf, expected);
+        Ok(())
+    }
+}
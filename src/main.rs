@@ -1,6 +1,8 @@
+mod alias;
 mod argv;
+mod config;
 mod kamp;
 
-fn main() -> anyhow::Result<()> {
-    kamp::run().map_err(From::from)
+fn main() -> std::process::ExitCode {
+    kamp::run()
 }